@@ -1,10 +1,11 @@
-use std::{thread, io, time, sync};
+use std::{thread, io, time, sync, fs};
+use std::path::PathBuf;
 
 use time::{Instant, Duration};
 use sync::{mpsc::{self, TryRecvError, RecvTimeoutError}};
 
-use crossterm::{terminal, execute};
-use log::{info};
+use crossterm::{terminal, execute, queue};
+use log::{info, warn};
 
 use crate::game::{*, ChangeAtom::*};
 use crate::pos::*;
@@ -12,11 +13,16 @@ use crate::util::*;
 use crate::sprites::sprite_tree::{*, InheritanceType::*};
 use crate::sprites::sprite_manager::SpriteManager;
 use crate::sprites::sprite::Sprite;
+use crate::sprites::{new_stn, Visibility, Visible, Invisible};
 use crate::id::*;
-use crate::deck::Card;
+use crate::deck::{Card, Variant};
+use crate::keymap::Keymap;
 
 mod card_repo;
-use card_repo::{make, EmbodiedCard, EmbodiedDeck};
+mod overlay;
+mod hud;
+mod panel;
+use card_repo::{make, ghost, CardRepo, EmbodiedCard, EmbodiedDeck};
 
 
 // Sent from main thread to animation thread
@@ -25,9 +31,38 @@ use card_repo::{make, EmbodiedCard, EmbodiedDeck};
 pub enum Msg {
     QuitMsg,
     Nop,
+
+    // Throw away everything on screen, e.g. before the first deal of a new game
+    Reset,
+
+    // Shows a box of text in the middle of the screen, e.g. the leaderboard. Sending the same
+    // lines again while they're up takes them back down.
+    ToggleOverlay(Vec<String>),
+
+    // Replaces the line of text along the bottom of the screen
+    Status(String),
+
+    // Labels every dealt card with the key that picks it, from here on
+    Keys(Keymap),
+
+    // The key hints along the bottom of the game over overlay, from here on
+    OverHints(String),
+
+    // Writes what's on screen to this file, as plain text
+    Screenshot(PathBuf),
+
+    // Moves the focus cursor's outline to this spot on the board, or hides it
+    Focus(Option<DealtPos>),
     ChangeMsg(ChangeSet)
 }
 
+// Sent from main thread straight to animation thread, which is the only one that knows what's
+// under the pointer
+pub enum Pointer {
+    Click(TermPos),
+    Move(TermPos)
+}
+
 pub enum BackMsg {
     QuitMsg,
 
@@ -323,14 +358,128 @@ pub fn sleep_until(i: Instant) {
 // Also, this game is russet, but rendering engine should be called russetry
 
 
-// fn write_time(ft: &mut FrameTree) {
-    // find time layer and write to it
-// }
+// Shows or hides one of the named layers of a card, e.g. "hint"
+fn change_activation(man: &SpriteManager, sprite_ids: &IdManager<Sprite>, card: Card, name: &str, v: Visibility) {
+    let node = sprite_ids.by_idkey((card, name).into())
+        .and_then(|id| man.tree.find_node(id));
+
+    if let Some(node) = node {
+        let mut sp = node.borrow_mut();
+        sp.set_visible(v);
+        sp.dirty_all();
+    }
+}
+
+// Swaps a card between its selected (active) and unselected (inactive) look
+fn show_selected(man: &SpriteManager, sprite_ids: &IdManager<Sprite>, card: Card, selected: bool) {
+    let (on, off) = if selected { (Visible, Invisible) } else { (Invisible, Visible) };
+    change_activation(man, sprite_ids, card, "active", on);
+    change_activation(man, sprite_ids, card, "inactive_card", off);
+    change_activation(man, sprite_ids, card, "inactive_border", off);
+}
+
+// Labels card with the key for pos, or hides its label if it's left the board or there's no key
+// for where it is
+fn show_key(man: &SpriteManager, sprite_ids: &IdManager<Sprite>, keys: Option<Keymap>, card: Card, pos: Option<DealtPos>) {
+    let node = sprite_ids.by_idkey((card, "key").into())
+        .and_then(|id| man.tree.find_node(id));
+
+    if let Some(node) = node {
+        let mut sp = node.borrow_mut();
+        match pos.zip(keys).and_then(|(p, k)| k.key(p)) {
+            Some(key) => {
+                sp.set_img(card_repo::key_label(key));
+                sp.set_visible(Visible);
+            },
+            None => sp.set_visible(Invisible)
+        };
+        sp.dirty_all();
+    }
+}
+
+// Builds a fresh copy of a card's sprites, ready to go on screen at pos
+fn embody(
+    man: &SpriteManager,
+    repo: &CardRepo,
+    tree_ids: &mut IdManager<SpriteTree>,
+    sprite_ids: &mut IdManager<Sprite>,
+    card: Card,
+    pos: TermPos
+) -> SpriteTree {
+    let EmbodiedCard {mut tree, tree_ids: _tree_ids, sprite_ids: _sprite_ids, ..} = repo.card(card);
+    tree.reanchor(pos, Children);
+    tree.register_dirt(Some(&man.dirt));
+    tree_ids.insert(card.into(), tree.id());
+    tree_ids.absorb(_tree_ids);
+    sprite_ids.absorb(_sprite_ids);
+    tree
+}
+
+// Detaches a card's whole tree from the screen, dirtying the area it used to cover
+fn take_card(man: &mut SpriteManager, tree_ids: &IdManager<SpriteTree>, card: Card) -> Option<SpriteTree> {
+    let tree = tree_ids.by_idkey(card.into()).and_then(|id| man.tree.remove_tree(id));
+    if let Some(ref tree) = tree { tree.dirty_all(); };
+    tree
+}
+
+// Moves the focus outline to pos, or hides it. It's made the first time it's needed, in front of
+// the cards, and stays put in the tree from then on, so overlays shown later stay in front of it.
+fn show_focus(man: &mut SpriteManager, focus_tree: &mut Option<Id<SpriteTree>>, pos: Option<DealtPos>, scale: Scale) {
+    if focus_tree.is_none() {
+        let mut tree = SpriteTree::new(new_stn(card_repo::focus_outline(scale)));
+        tree.set_visible(Invisible, Children);
+        tree.register_dirt(Some(&man.dirt));
+        *focus_tree = Some(tree.id());
+        man.tree.children_mut().insert(0, tree);
+        man.refresh_sprites();
+    }
+
+    if let Some(tree) = focus_tree.as_ref().and_then(|id| man.tree.tree_mut(*id)) {
+        tree.dirty_all();
+        match pos {
+            Some(pos) => {
+                // Over the card's own edges, which sit one up and one right of its spot
+                tree.reanchor(TermPos::from((&pos, &scale)) + TermPos::new(-1, 1), Children);
+                tree.set_visible(Visible, Children);
+            },
+            None => tree.set_visible(Invisible, Children)
+        };
+        tree.dirty_all();
+    }
+}
+
+// Cards under pos, as far as clicks are concerned. The one in front comes last.
+fn cards_at(man: &SpriteManager, sprite_ids: &IdManager<Sprite>, pos: TermPos) -> Vec<Card> {
+    let mut cards: Vec<Card> = man.tree
+        .collide(pos)
+        .into_iter()
+        .filter_map(|id| sprite_ids.by_id(id).and_then(|idk| idk.card))
+        .collect();
+    cards.dedup();
+    cards
+}
+
+// Moves the hover highlight onto card, or just takes it off whichever card had it
+fn set_hover(man: &SpriteManager, sprite_ids: &IdManager<Sprite>, hover: &mut Option<Card>, card: Option<Card>) {
+    if *hover == card { return; };
+    if let Some(old) = hover.take() {
+        change_activation(man, sprite_ids, old, "hover", Invisible);
+    };
+    if let Some(new) = card {
+        change_activation(man, sprite_ids, new, "hover", Visible);
+    };
+    *hover = card;
+}
+
+fn scale_for(variant: Variant) -> Scale {
+    SIZE_7.fit(variant.values.into())
+}
+
 
 
 pub fn animate(
     rcv: mpsc::Receiver<Msg>,
-    click_rcv: mpsc::Receiver<TermPos>,
+    pointer_rcv: mpsc::Receiver<Pointer>,
     snd: mpsc::Sender<BackMsg>
 ) -> Result<()> {
 
@@ -355,35 +504,58 @@ pub fn animate(
 
     info!("animation loop starting");
 
-    let repo9 = make(SIZE_9);
-    let repo7 = make(SIZE_7);
+    // Cards get wider when there are more shapes to fit on them, so the repo has to be rebuilt
+    // whenever a game starts with a different variant
+    let mut variant = Variant::STANDARD;
+    let mut scale = scale_for(variant);
+    let repo9 = make(SIZE_9, Variant::STANDARD);
+    let mut repo7 = make(scale, variant);
     let mut man = SpriteManager::default();
     let mut tree_ids: IdManager<SpriteTree> = Default::default();
     let mut sprite_ids: IdManager<Sprite> = Default::default();
 
-    loop {
+    // Set up by each NewGame, since a Reset throws it away along with everything else
+    let mut hud: Option<hud::Hud> = None;
+    let mut panel: Option<panel::Panel> = None;
+    let mut shown: Option<(Vec<String>, Id<SpriteTree>)> = None;
+    let mut status: Option<Id<SpriteTree>> = None;
+    let mut curtain: Option<Id<SpriteTree>> = None;
+
+    let mut focus_tree: Option<Id<SpriteTree>> = None;
+    let mut hover: Option<Card> = None;
+
+    // Outlast a Reset, it's the same player at the same keyboard
+    let mut keys: Option<Keymap> = None;
+    let mut over_hints = String::new();
+    let mut focus: Option<DealtPos> = None;
+
+    'frames: loop {
         let game_msg = rcv.recv_timeout(Duration::from_millis(10));
-        let click_msg = click_rcv.try_recv();
-
-        match click_msg {
-            Err(TryRecvError::Disconnected) => break,
-            Err(TryRecvError::Empty) => (),
-            Ok(pos) => {
-                info!("CLICK REGISTERED IN ANIMATION THREAD");
-                let mut cards: Vec<Card> = man.tree
-                    .collide(pos)
-                    .into_iter()
-                    .filter_map(|id| sprite_ids.by_id(id).map(|idk| idk.card).flatten())
-                    .collect();
-                cards.dedup();
-                info!("cards clicked: {:?}", cards);
-
-                snd.send(
-                    BackMsg::Collisions(cards)
-                );
+        // Everything the pointer did since the last frame. Every click is answered, but only the
+        // last move matters, so a quick swipe doesn't leave the highlight trailing behind.
+        let mut moved = None;
+        loop {
+            match pointer_rcv.try_recv() {
+                Err(TryRecvError::Disconnected) => break 'frames,
+                Err(TryRecvError::Empty) => break,
+                Ok(Pointer::Click(pos)) => {
+                    info!("CLICK REGISTERED IN ANIMATION THREAD");
+                    let cards = cards_at(&man, &sprite_ids, pos);
+                    info!("cards clicked: {:?}", cards);
+
+                    // Nobody left to tell, the game's over
+                    if snd.send(BackMsg::Collisions(cards)).is_err() { break 'frames; };
+                },
+                Ok(Pointer::Move(pos)) => moved = Some(pos)
             }
         }
 
+        // Highlights whichever card a click there would pick
+        if let Some(pos) = moved {
+            let card = cards_at(&man, &sprite_ids, pos).pop();
+            set_hover(&man, &sprite_ids, &mut hover, card);
+        };
+
         // SpriteManager should maybe spin up its own thread? Or no, that should be a different object, cuz we might have many sprite managers...
         // Actually, we could pretend that SpriteManager just writes straight to stdout, and even implement it that way at first, but later on
         // make a custom write object that sends writes to another thread to be written to terminal!
@@ -392,15 +564,78 @@ pub fn animate(
             Err(RecvTimeoutError::Disconnected) | Ok(Msg::QuitMsg) => break,
             Err(RecvTimeoutError::Timeout) => (),
             Ok(Msg::Nop) => continue,
+            Ok(Msg::Reset) => {
+                info!("RESET");
+                man = SpriteManager::default();
+                tree_ids = Default::default();
+                sprite_ids = Default::default();
+                hud = None;
+                panel = None;
+                shown = None;
+                status = None;
+                curtain = None;
+                focus_tree = None;
+                hover = None;
+                queue!(stdout, terminal::Clear(terminal::ClearType::All)).unwrap();
+            },
+            Ok(Msg::ToggleOverlay(lines)) => {
+                let old = shown.take();
+                if let Some(tree) = old.as_ref().and_then(|(_, id)| man.tree.remove_tree(*id)) {
+                    tree.dirty_all();
+                }
+
+                if old.is_none_or(|(old_lines, _)| old_lines != lines) {
+                    let mut tree = SpriteTree::new(new_stn(overlay::centered(&lines)));
+                    tree.register_dirt(Some(&man.dirt));
+                    shown = Some((lines, tree.id()));
+                    man.tree.children_mut().insert(0, tree);
+                }
+
+                man.refresh_sprites();
+            },
+            Ok(Msg::Status(text)) => {
+                if let Some(tree) = status.take().and_then(|id| man.tree.remove_tree(id)) {
+                    tree.dirty_all();
+                }
+
+                let mut tree = SpriteTree::new(new_stn(overlay::status(&text)));
+                tree.register_dirt(Some(&man.dirt));
+                status = Some(tree.id());
+                man.tree.push_tree(tree, INHERIT_NONE);
+                man.refresh_sprites();
+            },
+            Ok(Msg::Keys(map)) => keys = Some(map),
+            Ok(Msg::OverHints(hints)) => over_hints = hints,
+            Ok(Msg::Focus(pos)) => {
+                focus = pos;
+                show_focus(&mut man, &mut focus_tree, focus, scale);
+            },
+            Ok(Msg::Screenshot(path)) => {
+                let res = path.parent().map_or(Ok(()), fs::create_dir_all)
+                    .and_then(|_| fs::write(&path, man.text()));
+                match res {
+                    Ok(()) => info!("saved screenshot to {:?}", path),
+                    Err(err) => warn!("failed to save screenshot {:?}: {:?}", path, err)
+                }
+            },
             Ok(Msg::ChangeMsg(cs)) => {
-                let ChangeSet { changes, stamp: _ } = cs;
+                let ChangeSet { changes, stamp: _, score } = cs;
+                if let Some(ref mut hud) = hud { hud.set_score(score); };
+
+                // An already-found set only stays lit up until the next thing happens
+                if let Some(ref mut panel) = panel { panel.unflash(); };
                 info!("changeset: {:?}", changes);
                 for change in changes {
+                    // A card on the move is no longer under the pointer
+                    if let GoodMove(card, ..) | Unfind(card, _) | Undeal(card, _) | Reflow(card, ..) = change {
+                        if hover == Some(card) { set_hover(&man, &sprite_ids, &mut hover, None); };
+                    };
+
                     match change {
                         // Reflow(c, _, dst) => {
                         //     info!("REFLOW");
                         //     let card_buf = buf.tree_mut().find_mut(&(c.into())).unwrap();
-                        //     card_buf.set_anchor((&dst, &SIZE_7).finto());
+                        //     card_buf.set_anchor((&dst, &scale).finto());
                         // },
                        
                         // GoodMove(c, _, dst) => {
@@ -412,7 +647,7 @@ pub fn animate(
 
                             
                         //     let card_buf = buf.tree_mut().find_mut(&(c.into())).unwrap();
-                        //     card_buf.set_anchor((&dst, &SIZE_7).finto());
+                        //     card_buf.set_anchor((&dst, &scale).finto());
                         // },
                         
                         // BadOutline(c, _) => {
@@ -431,16 +666,185 @@ pub fn animate(
 
                         // Fade(Card, DealtPos),
                         
-                        Deal(card, pos) => {
-                            info!("DEAL");
-                            let EmbodiedCard {mut tree, tree_ids: _tree_ids, sprite_ids: _sprite_ids, ..}= repo7.card(card);
-                            tree.reanchor(TermPos::from((&pos, &SIZE_7)), Children);
+                        Select(card, _) => {
+                            info!("SELECT: {:?}", card);
+                            show_selected(&man, &sprite_ids, card, true);
+                        },
+
+                        // No outline animations yet, so a bad set just drops the selection
+                        Deselect(card, _) | BadOutline(card, _) => {
+                            info!("DESELECT: {:?}", card);
+                            show_selected(&man, &sprite_ids, card, false);
+                        },
+
+                        GoodMove(card, _, dst) => {
+                            info!("GOODMOVE");
+                            show_selected(&man, &sprite_ids, card, false);
+                            show_key(&man, &sprite_ids, keys, card, None);
+
+                            // Found cards go to the front, so each new set covers up the one
+                            // before it. If the set is undone, the old one just shows through again.
+                            if let Some(mut tree) = take_card(&mut man, &tree_ids, card) {
+                                tree.reanchor(TermPos::from((&dst, &scale)), Children);
+                                tree.dirty_all();
+                                man.tree.children_mut().insert(0, tree);
+                                man.refresh_sprites();
+                            }
+                        },
+
+                        Unfind(card, dst) => {
+                            info!("UNFIND");
+                            show_key(&man, &sprite_ids, keys, card, Some(dst));
+                            if let Some(mut tree) = take_card(&mut man, &tree_ids, card) {
+                                tree.reanchor(TermPos::from((&dst, &scale)), Children);
+                                tree.dirty_all();
+                                man.tree.children_mut().push(tree);
+                                man.refresh_sprites();
+                            }
+                        },
+
+                        Undeal(card, _) => {
+                            info!("UNDEAL");
+                            take_card(&mut man, &tree_ids, card);
+                            man.refresh_sprites();
+                        },
+
+                        Reflow(card, _, dst) => {
+                            info!("REFLOW");
+                            show_key(&man, &sprite_ids, keys, card, Some(dst));
+                            if let Some(tree) = tree_ids.by_idkey(card.into()).and_then(|id| man.tree.tree_mut(id)) {
+                                tree.dirty_all();
+                                tree.reanchor(TermPos::from((&dst, &scale)), Children);
+                                tree.dirty_all();
+                            }
+                        },
+
+                        Hint(card, _) => {
+                            info!("HINT: {:?}", card);
+                            change_activation(&man, &sprite_ids, card, "hint", Visible);
+                        },
+
+                        Unhint(card, _) => {
+                            change_activation(&man, &sprite_ids, card, "hint", Invisible);
+                        },
+
+                        Link(card) => {
+                            info!("LINK: {:?}", card);
+                            let mut sp = ghost(scale, card);
+                            sp.reanchor(TermPos::from((&GamePos::Link, &scale)));
+                            let mut tree = SpriteTree::new(new_stn(sp));
+                            tree.register_dirt(Some(&man.dirt));
+                            tree_ids.insert((card, "link").into(), tree.id());
+                            man.tree.push_tree(tree, INHERIT_NONE);
+                            man.refresh_sprites();
+                        },
+
+                        Unlink(card) => {
+                            let tree = tree_ids.by_idkey((card, "link").into())
+                                .and_then(|id| man.tree.remove_tree(id));
+                            if let Some(tree) = tree {
+                                tree.dirty_all();
+                                man.refresh_sprites();
+                            }
+                        },
+
+                        NewGame(seed, v) => {
+                            if v != variant {
+                                variant = v;
+                                scale = scale_for(variant);
+                                repo7 = make(scale, variant);
+                            }
+
+                            let mut tree = SpriteTree::new(new_stn(overlay::seed_label(seed)));
+                            tree.register_dirt(Some(&man.dirt));
+                            man.tree.push_tree(tree, INHERIT_NONE);
+                            man.refresh_sprites();
+                            hud = Some(hud::Hud::new(&mut man));
+                            show_focus(&mut man, &mut focus_tree, focus, scale);
+                        },
+
+                        Stats(stats) => {
+                            if let Some(ref mut hud) = hud { hud.update(stats); };
+                        },
+
+                        GameOver(stats) => {
+                            info!("GAME OVER: {:?}", stats);
+                            if let Some(ref mut hud) = hud { hud.stop(stats); };
+
+                            // Overlay goes at the front, so nothing gets drawn over it
+                            let mut tree = SpriteTree::new(new_stn(overlay::game_over(stats, &over_hints)));
                             tree.register_dirt(Some(&man.dirt));
-                            tree_ids.absorb(_tree_ids);
-                            sprite_ids.absorb(_sprite_ids);
+                            man.tree.children_mut().insert(0, tree);
+                            man.refresh_sprites();
+                        },
+
+                        Found(card, dst) => {
+                            info!("FOUND");
+                            let pos = TermPos::from((&dst, &scale));
+                            let tree = embody(&man, &repo7, &mut tree_ids, &mut sprite_ids, card, pos);
+                            man.tree.children_mut().insert(0, tree);
+                            man.refresh_sprites();
+                        },
+
+                        // Made on the first set found, since nothing says a game is a puzzle
+                        // until then
+                        Listed(card, set, slot) => {
+                            info!("LISTED: {:?}", card);
+                            panel.get_or_insert_with(|| panel::Panel::new(&scale))
+                                .list(&mut man, card, set, slot);
+                        },
+
+                        Unlisted(_, set, slot) => {
+                            if let Some(ref mut panel) = panel { panel.unlist(&mut man, set, slot); };
+                        },
 
+                        AlreadyFound(card, _, set) => {
+                            info!("ALREADY FOUND: {:?}", card);
+                            show_selected(&man, &sprite_ids, card, false);
+                            if let Some(ref mut panel) = panel { panel.flash(set); };
+                        },
+
+                        Claim(player, time) => {
+                            info!("CLAIM: player {}", player);
+                            if let Some(ref mut hud) = hud { hud.claim(player, time); };
+                        },
+
+                        Unclaim(_) => {
+                            if let Some(ref mut hud) = hud { hud.unclaim(); };
+                        },
+
+                        Player(player, score) => {
+                            if let Some(ref mut hud) = hud { hud.set_player(&mut man, player, score); };
+                        },
+
+                        // In front of everything, even overlays
+                        Pause => {
+                            if let Some(ref mut hud) = hud { hud.pause(); };
+                            if curtain.is_none() {
+                                let lines = [String::from("PAUSED")];
+                                let mut tree = SpriteTree::new(new_stn(overlay::curtain(&lines)));
+                                tree.register_dirt(Some(&man.dirt));
+                                curtain = Some(tree.id());
+                                man.tree.children_mut().insert(0, tree);
+                                man.refresh_sprites();
+                            }
+                        },
+
+                        Unpause => {
+                            if let Some(ref mut hud) = hud { hud.resume(); };
+                            if let Some(tree) = curtain.take().and_then(|id| man.tree.remove_tree(id)) {
+                                tree.dirty_all();
+                                man.refresh_sprites();
+                            }
+                        },
+
+                        Deal(card, dealt) => {
+                            info!("DEAL");
+                            let pos = TermPos::from((&dealt, &scale));
+                            let tree = embody(&man, &repo7, &mut tree_ids, &mut sprite_ids, card, pos);
                             man.tree.push_tree(tree, Inheritances {anchor: Children, order: Children, ..INHERIT_NONE} );
                             man.refresh_sprites();
+                            show_key(&man, &sprite_ids, keys, card, Some(dealt));
                         },
                         _ => ()
                     }
@@ -448,6 +852,7 @@ pub fn animate(
             }
         }
 
+        if let Some(ref mut hud) = hud { hud.tick(); };
         man.write(&mut stdout);
     }
 
@@ -1,4 +1,4 @@
-use crate::deck::{Card, CardShape, CardColor, CardFill, CardNumber, all_cards};
+use crate::deck::{Card, CardShape, CardColor, CardFill, CardNumber, Variant, all_cards};
 use crate::term_char::TermChar;
 use crate::pos::TermPos;
 use crate::util::{*, config::*};
@@ -34,8 +34,8 @@ pub struct CardRepo {
 }
 
 impl CardRepo {
-    pub fn new(scale: Scale) -> Self {
-        make(scale)
+    pub fn new(scale: Scale, variant: Variant) -> Self {
+        make(scale, variant)
     }
 
     pub fn card(&self, c: Card) -> EmbodiedCard {
@@ -121,10 +121,10 @@ pub fn stamp_question(scale: Scale, img: &mut Img, fg: Color, bg: Color) {
     set_s_clear(img, (drop, offset).finto(), String::from(scale.RAW_QUESTION), fg, bg);
 }
 
-pub fn stamp_shapes(scale: Scale, img: &Img, bg: Color) -> HashMap<Card, Img> {
+pub fn stamp_shapes(scale: Scale, img: &Img, bg: Color, variant: Variant) -> HashMap<Card, Img> {
     let mut card_bufs = HashMap::new();
 
-    for card in all_cards() {
+    for card in all_cards(variant) {
         let mut clon = img.clone();
         stamp_shape(scale, &mut clon, card, bg);
         card_bufs.insert(card, clon);
@@ -244,7 +244,59 @@ pub fn card_base(
     img
 }
 
-pub fn make(scale: Scale) -> CardRepo {
+// Just the outline and shapes of a card, drawn straight onto the terminal background. For
+// showing a card that isn't actually in play, so it has no ids and can't be clicked.
+pub fn ghost(scale: Scale, card: Card) -> Sprite {
+    let mut img = card_base(scale, CARD_BORDER, TERM_BG, Some(TERM_BG));
+    stamp_shape(scale, &mut img, card, TERM_BG);
+
+    let mut sp: Sprite = img.into();
+    sp.set_clickable(Unclickable);
+    sp
+}
+
+// Frame around the card under the focus cursor. Drawn over the card's own edges, where the hint
+// outline goes, but with the terminal background behind it so the two can be told apart.
+pub fn focus_outline(scale: Scale) -> Sprite {
+    let mut sp: Sprite = card_base(scale, FOCUS, TERM_BG, None).into();
+    sp.set_clickable(Unclickable);
+    sp
+}
+
+// A card shrunk down to one line of text, for the puzzle panel: one symbol per shape, in the
+// card's color. Each symbol stands for a shape and fill together, rather than looking like them.
+pub fn mini(card: Card, bg: Color) -> Img {
+    let sym = match (card.shape, card.fill) {
+        (CardShape::Oval, CardFill::Solid) => '●',
+        (CardShape::Oval, CardFill::Striped) => '◍',
+        (CardShape::Oval, CardFill::Empty) => '○',
+        (CardShape::Oval, CardFill::Dotted) => '◌',
+        (CardShape::Diamond, CardFill::Solid) => '◆',
+        (CardShape::Diamond, CardFill::Striped) => '◈',
+        (CardShape::Diamond, CardFill::Empty) => '◇',
+        (CardShape::Diamond, CardFill::Dotted) => '⋄',
+        (CardShape::Squiggle, CardFill::Solid) => '■',
+        (CardShape::Squiggle, CardFill::Striped) => '▩',
+        (CardShape::Squiggle, CardFill::Empty) => '□',
+        (CardShape::Squiggle, CardFill::Dotted) => '⬚',
+        (CardShape::Hourglass, CardFill::Solid) => '▲',
+        (CardShape::Hourglass, CardFill::Striped) => '◭',
+        (CardShape::Hourglass, CardFill::Empty) => '△',
+        (CardShape::Hourglass, CardFill::Dotted) => '◬'
+    };
+
+    let num: i16 = card.number.into();
+    let shapes: String = std::iter::repeat_n(sym, num.finto()).collect();
+    Img::text(&format!(" {:^4} ", shapes), get_raw_color(card), bg)
+}
+
+// Badge for the key that picks a card, drawn over its top left corner. Dark on both the plain and
+// selected card, so it reads the same either way.
+pub fn key_label(key: char) -> Img {
+    Img::text(&key.to_string(), OVERLAY_FG, OVERLAY_BG)
+}
+
+pub fn make(scale: Scale, variant: Variant) -> CardRepo {
     let mut outline_thin = card_base(scale, CARD_BORDER, TERM_BG, None);
     // outline_thin.set_anchor((1, -1).finto());
 
@@ -253,6 +305,8 @@ pub fn make(scale: Scale) -> CardRepo {
 
     let mut outline_good = card_base(scale, GOOD_SET, GOOD_SET, None);
     let mut outline_bad = card_base(scale, BAD_SET, BAD_SET, None);
+    let outline_hint = card_base(scale, HINT, CARD_BG, None);
+    let outline_hover = card_base(scale, HOVER, CARD_BG, None);
 
     let card_active = card_base(scale, ACTIVE_BG, ACTIVE_BG, Some(ACTIVE_BG));
     let card_inactive = card_base(scale, CARD_BG, CARD_BG, Some(CARD_BG));
@@ -263,8 +317,8 @@ pub fn make(scale: Scale) -> CardRepo {
     let mut deck_inactive = card_inactive.clone();
     stamp_question(scale, &mut deck_inactive, TERM_BG, TERM_BG);
 
-    let mut cards_active = stamp_shapes(scale, &card_active, ACTIVE_BG);
-    let cards_inactive = stamp_shapes(scale, &card_inactive, CARD_BG);
+    let mut cards_active = stamp_shapes(scale, &card_active, ACTIVE_BG, variant);
+    let cards_inactive = stamp_shapes(scale, &card_inactive, CARD_BG, variant);
 
     let mut cards = HashMap::new();
 
@@ -275,6 +329,17 @@ pub fn make(scale: Scale) -> CardRepo {
         let mut sprite_ids: IdManager<Sprite> = IdManager::default();
         let mut tree = SpriteTree::default();
 
+        // Key label goes in first, so it's in front of everything else. Left blank and hidden,
+        // since which key it shows depends on where the card is dealt.
+        let mut key: Sprite = key_label(' ').into();
+        key.reanchor((1, 2).finto());
+        key.set_visible(Invisible);
+        sprite_ids.insert((handle, "key").into(), key.id());
+        tree_ids.insert(
+            (handle, "key").into(),
+            tree.push_sprite(new_stn(key), INHERIT_NONE)
+        );
+
         let mut active_card: Sprite = cards_active.remove(&handle).unwrap().into();
         active_card.set_visible(Invisible);
         sprite_ids.insert((handle, "active").into(), active_card.id());
@@ -294,9 +359,32 @@ pub fn make(scale: Scale) -> CardRepo {
         sprite_ids.insert((handle, "inactive_card").into(), inactive_card.id());
         sprite_ids.insert((handle, "inactive_border").into(), inactive_border.id());
 
+        // Hint outline sits right on top of the inactive card's edges, hidden until needed.
+        let mut hint_outline: Sprite = outline_hint.clone().into();
+        hint_outline.reanchor((-1, 1).finto());
+        hint_outline.reorder(2);
+        hint_outline.set_visible(Invisible);
+        sprite_ids.insert((handle, "hint").into(), hint_outline.id());
+
+        // Hover outline goes in the same place, behind the hint, so a hint always shows
+        let mut hover_outline: Sprite = outline_hover.clone().into();
+        hover_outline.reanchor((-1, 1).finto());
+        hover_outline.reorder(2);
+        hover_outline.set_visible(Invisible);
+        sprite_ids.insert((handle, "hover").into(), hover_outline.id());
+
         let mut inactive_subtree = SpriteTree::default();
         tree_ids.insert((handle, "inactive").into(), inactive_subtree.id());
 
+        tree_ids.insert(
+            (handle, "hint").into(),
+            inactive_subtree.push_sprite(new_stn(hint_outline), INHERIT_NONE)
+        );
+        tree_ids.insert(
+            (handle, "hover").into(),
+            inactive_subtree.push_sprite(new_stn(hover_outline), INHERIT_NONE)
+        );
+
         tree_ids.insert(
             (handle, "inactive_card").into(),
             inactive_subtree.push_sprite(new_stn(inactive_card), INHERIT_NONE)
@@ -319,7 +407,8 @@ fn get_raw_shape(c:Card, s:Scale) -> &'static str {
     match c.shape {
         CardShape::Oval => s.RAW_OVAL,
         CardShape::Diamond => s.RAW_DIAMOND,
-        CardShape::Squiggle => s.RAW_SQUIGGLE
+        CardShape::Squiggle => s.RAW_SQUIGGLE,
+        CardShape::Hourglass => s.RAW_HOURGLASS
     }
 }
 
@@ -335,7 +424,8 @@ fn get_raw_color(c: Card) -> Color {
     match c.color {
         CardColor::Color1 => COLOR_1,
         CardColor::Color2 => COLOR_2,
-        CardColor::Color3 => COLOR_3
+        CardColor::Color3 => COLOR_3,
+        CardColor::Color4 => COLOR_4
     }
 }
 
@@ -347,6 +437,7 @@ fn get_raw_char(card: Card, ch: char, colr: Color, card_bg: Color) -> SpriteCell
         (_, 'X') =>                 Opaque(TermChar::new(' ', colr, colr)),
         (CardFill::Striped, 'o') => Opaque(TermChar::new('╳', colr, card_bg)),
         (CardFill::Empty, 'o') =>   Opaque(TermChar::new(' ', card_bg, card_bg)),
+        (CardFill::Dotted, 'o') =>  Opaque(TermChar::new('·', colr, card_bg)),
         _ =>                        panic!("Unrecognized character in get_raw_char")
     }
 }
@@ -0,0 +1,194 @@
+use std::time::{Duration, Instant};
+
+use crate::game::GameStats;
+use crate::score::{PlayerScore, Score};
+use crate::util::*;
+
+use crate::sprites::sprite_tree::*;
+use crate::sprites::sprite_manager::SpriteManager;
+use crate::sprites::img::Img;
+use crate::sprites::*;
+
+// Counters along the bottom row of the screen, to the right of the seed label. Each one is its
+// own sprite, padded to a fixed width, so changing one only dirties its own few cells.
+// Multiplayer games get another row along the top, one counter per player.
+
+// Leaves room for "seed " plus the longest u64
+const HUD_LEFT: i16 = WIN_MARGIN_HORIZ + 28;
+const TIMER_WIDTH: i16 = 20;
+const SETS_WIDTH: i16 = 12;
+const CARDS_WIDTH: i16 = 16;
+const SCORE_WIDTH: i16 = 20;
+const PLAYER_WIDTH: i16 = 28;
+
+// How long the last change in score is shown next to it
+const SCORE_CHANGE_SHOWN: Duration = Duration::from_secs(2);
+
+struct Counter {
+    stn: Stn,
+    width: usize,
+    text: String
+}
+
+impl Counter {
+    fn new(man: &mut SpriteManager, y: i16, x: i16, width: i16) -> Self {
+        let width: usize = width.finto();
+        let mut sp: sprite::Sprite = Img::text(&" ".repeat(width), OVERLAY_FG, TERM_BG).into();
+        sp.reanchor((y, x).finto());
+        sp.set_clickable(Unclickable);
+
+        let stn = new_stn(sp);
+        let mut tree = SpriteTree::new(stn.clone());
+        tree.register_dirt(Some(&man.dirt));
+        man.tree.push_tree(tree, INHERIT_NONE);
+
+        Self { stn, width, text: String::new() }
+    }
+
+    // Only touches the sprite if the text actually changed
+    fn show(&mut self, text: String) {
+        if text == self.text { return; };
+        let padded = format!("{:<width$}", text, width = self.width);
+        self.stn.borrow_mut().set_img(Img::text(&padded, OVERLAY_FG, TERM_BG));
+        self.text = text;
+    }
+}
+
+pub struct Hud {
+    timer: Counter,
+    sets: Counter,
+    cards: Counter,
+    score: Counter,
+
+    // Latest stats from the game, and when they arrived. The clock keeps running from there
+    // until the game ends, and holds still while it's paused (since when).
+    stats: GameStats,
+    received: Instant,
+    stopped: bool,
+    paused: Option<Instant>,
+
+    total: i32,
+    change: Option<(i32, Instant)>,
+
+    // Multiplayer only, made as each player's first score comes in. The claim is who has the
+    // board, how long for, and since when.
+    players: Vec<(Counter, PlayerScore)>,
+    claim: Option<(u8, Duration, Instant)>
+}
+
+impl Hud {
+    pub fn new(man: &mut SpriteManager) -> Self {
+        let (height, _) = TS.dims();
+        let y = height - 1;
+        let timer = Counter::new(man, y, HUD_LEFT, TIMER_WIDTH);
+        let sets = Counter::new(man, y, HUD_LEFT + TIMER_WIDTH, SETS_WIDTH);
+        let cards = Counter::new(man, y, HUD_LEFT + TIMER_WIDTH + SETS_WIDTH, CARDS_WIDTH);
+        let score = Counter::new(man, y, HUD_LEFT + TIMER_WIDTH + SETS_WIDTH + CARDS_WIDTH, SCORE_WIDTH);
+        man.refresh_sprites();
+
+        Self {
+            timer, sets, cards, score,
+            stats: GameStats::default(),
+            received: Instant::now(),
+            stopped: false,
+            paused: None,
+            total: 0,
+            change: None,
+            players: vec![],
+            claim: None
+        }
+    }
+
+    pub fn set_player(&mut self, man: &mut SpriteManager, player: u8, score: PlayerScore) {
+        let i = usize::from(player);
+        while self.players.len() <= i {
+            let x = WIN_MARGIN_HORIZ + PLAYER_WIDTH * i16::ffrom(self.players.len());
+            self.players.push((Counter::new(man, 0, x, PLAYER_WIDTH), PlayerScore::default()));
+            man.refresh_sprites();
+        }
+        self.players[i].1 = score;
+    }
+
+    pub fn claim(&mut self, player: u8, time: Duration) {
+        self.claim = Some((player, time, Instant::now()));
+    }
+
+    pub fn unclaim(&mut self) {
+        self.claim = None;
+    }
+
+    pub fn set_score(&mut self, score: Score) {
+        let total = score.total();
+        if total != self.total {
+            self.change = Some((total - self.total, Instant::now()));
+            self.total = total;
+        }
+    }
+
+    pub fn update(&mut self, stats: GameStats) {
+        if self.stopped { return; };
+        self.stats = stats;
+        self.received = Instant::now();
+    }
+
+    // Freezes the clock at the game's final time
+    pub fn stop(&mut self, stats: GameStats) {
+        self.update(stats);
+        self.stopped = true;
+    }
+
+    pub fn pause(&mut self) {
+        self.paused.get_or_insert_with(Instant::now);
+    }
+
+    // Whoever had the board gets back all the time they had left
+    pub fn resume(&mut self) {
+        if let Some(since) = self.paused.take() {
+            if let Some((_, _, ref mut claimed)) = self.claim { *claimed += since.elapsed(); };
+        };
+    }
+
+    // Called every frame
+    pub fn tick(&mut self) {
+        let elapsed = if self.stopped || self.paused.is_some() {
+            self.stats.elapsed
+        } else {
+            self.stats.elapsed + self.received.elapsed()
+        };
+
+        let mut time = format!("time {}", fmt_duration(elapsed));
+        if self.stats.penalty > Duration::ZERO {
+            time.push_str(&format!(" (+{})", fmt_duration(self.stats.penalty)));
+        }
+
+        self.timer.show(time);
+        // Puzzles never deal, so what's left is sets rather than cards
+        if self.stats.sets_to_find > 0 {
+            self.sets.show(format!("sets {}/{}", self.stats.sets_found, self.stats.sets_to_find));
+            self.cards.show(format!("sets left {}", self.stats.sets_to_find.saturating_sub(self.stats.sets_found)));
+        } else {
+            self.sets.show(format!("sets {}", self.stats.sets_found));
+            self.cards.show(format!("cards left {}", self.stats.cards_left));
+        }
+
+        match self.change {
+            Some((diff, when)) if when.elapsed() < SCORE_CHANGE_SHOWN =>
+                self.score.show(format!("score {} ({:+})", self.total, diff)),
+            _ => self.score.show(format!("score {}", self.total))
+        }
+
+        // Whoever has the board gets a countdown. It stops at zero, and main sends the Timeout.
+        for (i, (counter, p)) in self.players.iter_mut().enumerate() {
+            let mut text = format!("P{}: {} ({} sets)", i + 1, p.score.total(), p.sets_found);
+            match self.claim {
+                Some((player, time, since)) if usize::from(player) == i => {
+                    let held = self.paused.unwrap_or_else(Instant::now).saturating_duration_since(since);
+                    let left = time.saturating_sub(held);
+                    text.push_str(&format!(" [{:.1}s]", left.as_secs_f64()));
+                },
+                _ => ()
+            };
+            counter.show(text);
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use crate::game::GameStats;
+use crate::util::*;
+
+use crate::sprites::sprite::Sprite;
+use crate::sprites::img::Img;
+use crate::sprites::*;
+
+// Overlays are plain boxes of text, centered on the screen. They're unclickable, so they don't
+// swallow clicks meant for whatever is underneath.
+
+// Pads every line to the same width, with a margin of blank space all the way around
+fn boxed(lines: &[String]) -> String {
+    let width = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+    let blank = " ".repeat(width + 4);
+
+    let mut ret = format!("{}\n", blank);
+    for l in lines {
+        ret.push_str(&format!("  {:<width$}  \n", l, width = width));
+    }
+    ret.push_str(&blank);
+    ret
+}
+
+pub fn centered(lines: &[String]) -> Sprite {
+    let img = Img::text(&boxed(lines), OVERLAY_FG, OVERLAY_BG);
+    let (height, width) = TS.dims();
+    let y = (height - i16::ffrom(img.height())) / 2;
+    let x = (width - i16::ffrom(img.width())) / 2;
+
+    let mut sp: Sprite = img.into();
+    sp.reanchor((y, x).finto());
+    sp.set_clickable(Unclickable);
+    sp
+}
+
+// hints are built from the bindings on the main thread, see Msg::OverHints
+pub fn game_over(stats: GameStats, hints: &str) -> Sprite {
+    centered(&[
+        String::from("GAME OVER"),
+        String::new(),
+        format!("Score:      {}", stats.score),
+        format!("Seed:       {}", stats.seed),
+        format!("Sets found: {}", stats.sets_found),
+        format!("Time:       {}", fmt_duration(stats.elapsed)),
+        format!("Hints:      {} (+{})", stats.hints, fmt_duration(stats.penalty)),
+        String::new(),
+        String::from(hints),
+    ])
+}
+
+// Small reminder of which deal this is, tucked into the bottom left corner
+pub fn seed_label(seed: u64) -> Sprite {
+    let img = Img::text(&format!("seed {}", seed), OVERLAY_FG, TERM_BG);
+    let (height, _) = TS.dims();
+
+    let mut sp: Sprite = img.into();
+    sp.reanchor((height - 1, WIN_MARGIN_HORIZ).finto());
+    sp.set_clickable(Unclickable);
+    sp
+}
+
+// A line of text along the bottom of the screen, for modes that don't have a HUD
+pub fn status(text: &str) -> Sprite {
+    let img = Img::text(text, OVERLAY_FG, TERM_BG);
+    let (height, _) = TS.dims();
+
+    let mut sp: Sprite = img.into();
+    sp.reanchor((height - 1, WIN_MARGIN_HORIZ).finto());
+    sp.set_clickable(Unclickable);
+    sp
+}
+
+// Covers the whole screen, so a paused game can't be studied for free
+pub fn curtain(lines: &[String]) -> Sprite {
+    let (height, width) = TS.dims();
+    let (height, width): (usize, usize) = (height.finto(), width.finto());
+    let top = height.saturating_sub(lines.len()) / 2;
+
+    let mut text = String::new();
+    for y in 0..height {
+        let line = y.checked_sub(top).and_then(|i| lines.get(i)).map_or("", |l| l.as_str());
+        let pad = width.saturating_sub(line.chars().count()) / 2;
+        text.push_str(&format!("{:<width$}\n", format!("{}{}", " ".repeat(pad), line), width = width));
+    }
+
+    let mut sp: Sprite = Img::text(&text, OVERLAY_FG, TERM_BG).into();
+    sp.reanchor((0, 0).finto());
+    sp.set_clickable(Unclickable);
+    sp
+}
@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+
+use crossterm::style::Color;
+
+use crate::deck::Card;
+use crate::id::Id;
+use crate::pos::*;
+use crate::util::*;
+
+use crate::sprites::sprite_tree::*;
+use crate::sprites::sprite_manager::SpriteManager;
+use crate::sprites::*;
+
+use super::card_repo::mini;
+
+// Puzzle only: every set found so far, one row each, over to the right where the extra columns
+// would be in a regular game. Every card gets its own little sprite from card_repo::mini, since a
+// card can turn up in more than one set.
+
+// Wide enough for four shapes with a space either side, plus a gap
+const SLOT_WIDTH: i16 = 7;
+const ROW_HEIGHT: i16 = 2;
+
+pub struct Panel {
+    origin: TermPos,
+    cards: HashMap<(u8, u8), (Card, Stn, Id<SpriteTree>)>,
+
+    // Row lit up because its set was just found again
+    flashed: Option<u8>
+}
+
+impl Panel {
+    pub fn new(scale: &Scale) -> Self {
+        Self {
+            origin: TermPos::from((&DealtPos::new(0, 4), scale)) + (1, 1).finto(),
+            cards: HashMap::new(),
+            flashed: None
+        }
+    }
+
+    pub fn list(&mut self, man: &mut SpriteManager, card: Card, set: u8, slot: u8) {
+        let (row, col): (i16, i16) = (set.into(), slot.into());
+        let mut sp: sprite::Sprite = mini(card, CARD_BG).into();
+        sp.reanchor(self.origin + (row * ROW_HEIGHT, col * SLOT_WIDTH).finto());
+        sp.set_clickable(Unclickable);
+
+        let stn = new_stn(sp);
+        let mut tree = SpriteTree::new(stn.clone());
+        tree.register_dirt(Some(&man.dirt));
+        let id = tree.id();
+        man.tree.push_tree(tree, INHERIT_NONE);
+        man.refresh_sprites();
+
+        self.cards.insert((set, slot), (card, stn, id));
+    }
+
+    pub fn unlist(&mut self, man: &mut SpriteManager, set: u8, slot: u8) {
+        if let Some(tree) = self.cards.remove(&(set, slot)).and_then(|(_, _, id)| man.tree.remove_tree(id)) {
+            tree.dirty_all();
+            man.refresh_sprites();
+        }
+    }
+
+    // Lights up a row, putting any other lit row back to normal
+    pub fn flash(&mut self, set: u8) {
+        self.unflash();
+        self.paint(set, HINT);
+        self.flashed = Some(set);
+    }
+
+    pub fn unflash(&mut self) {
+        if let Some(set) = self.flashed.take() {
+            self.paint(set, CARD_BG);
+        }
+    }
+
+    fn paint(&self, set: u8, bg: Color) {
+        for (_, (card, stn, _)) in self.cards.iter().filter(|((s, _), _)| *s == set) {
+            stn.borrow_mut().set_img(mini(*card, bg));
+        }
+    }
+}
@@ -0,0 +1,396 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use crossterm::event::{KeyCode, KeyModifiers};
+
+use crate::cursor::Dir;
+use crate::input::Chord;
+use crate::keymap::Keymap;
+use crate::stats::data_dir;
+use crate::util::*;
+
+// Which keys do what, besides picking cards. Read at startup from bindings.txt in the data
+// directory, one action per line, followed by every key that should do it:
+//
+//     # comments and blank lines are skipped
+//     undo    ctrl+z  F5
+//     pause   Space
+//
+// An action that's listed loses its default keys, one that isn't keeps them. Keys are single
+// characters or key names (Enter, Esc, F1, ...), with any of ctrl+, alt+ and shift+ in front.
+//
+// Card keys are checked first and can't be rebound, so a default that clashes with the keymap is
+// just dropped. On Dvorak, u is a card, which leaves ctrl+z for undo.
+//
+// The cursor's keys are the exception. They're kept apart from everything else, and come before
+// card keys and other actions while the cursor is showing, so hjkl still steer it on QWERTY
+// where h is a card, and l can go on being the leaderboard until then.
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    Hint,
+    AddCards,
+    NewGame,
+    Undo,
+    Redo,
+    DeselectAll,
+    Pause,
+    Screenshot,
+    Leaderboard,
+    Help,
+
+    // The focus cursor, see cursor
+    Left,
+    Right,
+    Up,
+    Down,
+    Choose
+}
+
+impl Action {
+    pub const ALL: [Action; 16] = [
+        Self::Quit, Self::Hint, Self::AddCards, Self::NewGame, Self::Undo, Self::Redo,
+        Self::DeselectAll, Self::Pause, Self::Screenshot, Self::Leaderboard, Self::Help,
+        Self::Left, Self::Right, Self::Up, Self::Down, Self::Choose
+    ];
+
+    // As written in the bindings file
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Quit => "quit",
+            Self::Hint => "hint",
+            Self::AddCards => "add-cards",
+            Self::NewGame => "new-game",
+            Self::Undo => "undo",
+            Self::Redo => "redo",
+            Self::DeselectAll => "deselect",
+            Self::Pause => "pause",
+            Self::Screenshot => "screenshot",
+            Self::Leaderboard => "leaderboard",
+            Self::Help => "help",
+            Self::Left => "left",
+            Self::Right => "right",
+            Self::Up => "up",
+            Self::Down => "down",
+            Self::Choose => "choose"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|a| a.name() == name)
+    }
+
+    // Which way this moves the cursor, for the ones bound apart from the rest
+    pub fn dir(self) -> Option<Dir> {
+        match self {
+            Self::Left => Some(Dir::Left),
+            Self::Right => Some(Dir::Right),
+            Self::Up => Some(Dir::Up),
+            Self::Down => Some(Dir::Down),
+            _ => None
+        }
+    }
+
+    // For the help overlay
+    fn describe(self) -> &'static str {
+        match self {
+            Self::Quit => "quit",
+            Self::Hint => "show one card of a set",
+            Self::AddCards => "deal three more cards",
+            // Until then it does what choose does, so Enter can pick cards too
+            Self::NewGame => "new game when over, else pick",
+            Self::Undo => "undo",
+            Self::Redo => "redo",
+            Self::DeselectAll => "deselect everything",
+            Self::Pause => "pause the clock",
+            Self::Screenshot => "save the screen as text",
+            Self::Leaderboard => "leaderboard",
+            Self::Help => "this help",
+            Self::Left => "cursor left",
+            Self::Right => "cursor right",
+            Self::Up => "cursor up",
+            Self::Down => "cursor down",
+            Self::Choose => "pick the card under the cursor"
+        }
+    }
+
+    fn defaults(self) -> &'static [&'static str] {
+        match self {
+            Self::Quit => &["Backspace", "Delete", "ctrl+c"],
+            Self::Hint => &["?", "/"],
+            // '=' is just '+' without shift
+            Self::AddCards => &["+", "="],
+            Self::NewGame => &["Enter"],
+            Self::Undo => &["u", "ctrl+z"],
+            Self::Redo => &["U", "ctrl+y"],
+            Self::DeselectAll => &["Esc"],
+            Self::Pause => &["p", "ctrl+p"],
+            Self::Screenshot => &["ctrl+s", "F12"],
+            Self::Leaderboard => &["l"],
+            Self::Help => &["F1"],
+            Self::Left => &["Left", "h"],
+            Self::Right => &["Right", "l"],
+            Self::Up => &["Up", "k"],
+            Self::Down => &["Down", "j"],
+            Self::Choose => &["Space"]
+        }
+    }
+}
+
+fn bad(line: usize, msg: &str) -> SetError {
+    SetError::new(SetErrorKind::BadBindings, &format!("line {}: {}", line, msg))
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bindings {
+    map: HashMap<Chord, Action>,
+
+    // Moving the cursor, see Action::dir
+    cursor: HashMap<Chord, Action>
+}
+
+impl Bindings {
+    // Every default that doesn't clash with keys
+    pub fn defaults(keys: Keymap) -> Self {
+        let mut ret = Self { map: HashMap::new(), cursor: HashMap::new() };
+        for action in Action::ALL {
+            for chord in action.defaults().iter().filter_map(|k| Chord::parse(k)) {
+                if !reserved(keys, action, chord) {
+                    ret.layer_mut(action).insert(chord, action);
+                };
+            }
+        }
+        ret
+    }
+
+    pub fn from_text(text: &str, keys: Keymap) -> Result<Self> {
+        let mut listed: Vec<(Chord, Action, usize)> = vec![];
+        let mut replaced: Vec<Action> = vec![];
+
+        for (i, line) in text.lines().enumerate() {
+            let n = i + 1;
+            let line = line.split('#').next().unwrap_or("");
+            let mut words = line.split_whitespace();
+            let action = match words.next() {
+                Some(word) => Action::from_name(word)
+                    .ok_or_else(|| bad(n, &format!("no action called {:?}", word)))?,
+                None => continue
+            };
+            replaced.push(action);
+
+            for word in words {
+                let chord = Chord::parse(word).ok_or_else(|| bad(n, &format!("can't read key {:?}", word)))?;
+                if reserved(keys, action, chord) {
+                    return Err(bad(n, &format!("{} is taken by a card or claim key", chord)));
+                };
+                let clash = |&&(c, a, _): &&(Chord, Action, usize)|
+                    c == chord && a != action && a.dir().is_some() == action.dir().is_some();
+                match listed.iter().find(clash) {
+                    Some((_, other, m)) =>
+                        return Err(bad(n, &format!("{} is already bound to {} on line {}", chord, other.name(), m))),
+                    None => listed.push((chord, action, n))
+                };
+            }
+        }
+
+        // Defaults only fill in for actions the file leaves alone
+        let mut ret = Self::defaults(keys);
+        ret.map.retain(|_, a| !replaced.contains(a));
+        ret.cursor.retain(|_, a| !replaced.contains(a));
+        for (chord, action, n) in listed {
+            match ret.layer_mut(action).insert(chord, action) {
+                Some(other) if other != action =>
+                    return Err(bad(n, &format!("{} is already bound to {} by default", chord, other.name()))),
+                _ => ()
+            };
+        }
+
+        if !ret.map.values().any(|&a| a == Action::Quit) {
+            return Err(SetError::new(SetErrorKind::BadBindings, "nothing left to quit with"));
+        };
+
+        Ok(ret)
+    }
+
+    // A file that isn't there just means the defaults
+    pub fn load(path: &Path, keys: Keymap) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::from_text(&text, keys),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::defaults(keys)),
+            Err(err) => Err(err.into())
+        }
+    }
+
+    fn layer_mut(&mut self, action: Action) -> &mut HashMap<Chord, Action> {
+        if action.dir().is_some() { &mut self.cursor } else { &mut self.map }
+    }
+
+    // What chord does, other than moving the cursor
+    pub fn action(&self, chord: Chord) -> Option<Action> {
+        self.map.get(&chord).copied()
+    }
+
+    // Which way chord moves the cursor
+    pub fn cursor_dir(&self, chord: Chord) -> Option<Dir> {
+        self.cursor.get(&chord).and_then(|a| a.dir())
+    }
+
+    // Cursor keys that mean something else while the cursor's hidden
+    fn shared(&self, keys: Keymap, chord: Chord) -> bool {
+        self.map.contains_key(&chord) || card_key(keys, chord)
+    }
+
+    // Cursor keys that can be held down. Shared ones can't, or holding l with the cursor hidden
+    // would flick the leaderboard on and off.
+    pub fn repeatable(&self, keys: Keymap) -> Vec<Chord> {
+        self.cursor.keys().copied().filter(|&c| !self.shared(keys, c)).collect()
+    }
+
+    // Every key bound to action, in a fixed order so the help doesn't shuffle
+    fn keys_for(&self, action: Action) -> Vec<String> {
+        let layer = if action.dir().is_some() { &self.cursor } else { &self.map };
+        let mut ret: Vec<String> = layer.iter()
+            .filter(|&(_, &a)| a == action)
+            .map(|(c, _)| c.to_string())
+            .collect();
+        ret.sort();
+        ret
+    }
+
+    // Key hints for the bottom of an overlay, like "l: leaderboard    Backspace: quit", leaving
+    // out actions with nothing bound
+    pub fn hints(&self, hints: &[(Action, &str)]) -> String {
+        let hints: Vec<String> = hints.iter()
+            .filter_map(|&(action, what)| self.keys_for(action).first().map(|k| format!("{}: {}", k, what)))
+            .collect();
+        hints.join("    ")
+    }
+
+    // Lines for the help overlay
+    pub fn help(&self, keys: Keymap) -> Vec<String> {
+        let mut ret = vec![String::from("KEYS"), String::new()];
+        ret.push(format!("{:<32}{} layout, or click", "pick a card", keys.name()));
+        for action in Action::ALL {
+            let bound = self.keys_for(action);
+            let bound = if bound.is_empty() { String::from("(none)") } else { bound.join(", ") };
+            ret.push(format!("{:<32}{}", action.describe(), bound));
+        }
+
+        let mut shared: Vec<String> = self.cursor.keys()
+            .filter(|&&c| self.shared(keys, c))
+            .map(|c| c.to_string())
+            .collect();
+        if !shared.is_empty() {
+            shared.sort();
+            ret.push(String::new());
+            ret.push(format!("{} only move the cursor once it's showing.", shared.join(", ")));
+            ret.push(String::from("Esc or a click puts it away again."));
+        };
+        ret
+    }
+}
+
+fn card_key(keys: Keymap, chord: Chord) -> bool {
+    match chord {
+        Chord { code: KeyCode::Char(c), mods: KeyModifiers::NONE } => keys.pos(c).is_some(),
+        _ => false
+    }
+}
+
+// Claim keys are read before any binding, and card keys before anything but the cursor, so
+// nothing unmodified can go on them
+fn reserved(keys: Keymap, action: Action, chord: Chord) -> bool {
+    let claim = matches!(chord, Chord { code: KeyCode::Char('1'..='4'), mods: KeyModifiers::NONE });
+    claim || (action.dir().is_none() && card_key(keys, chord))
+}
+
+pub fn default_path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("bindings.txt"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chord(s: &str) -> Chord {
+        Chord::parse(s).unwrap()
+    }
+
+    fn error(text: &str) -> String {
+        Bindings::from_text(text, Keymap::Qwerty).unwrap_err().msg
+    }
+
+    #[test]
+    fn listed_actions_lose_their_defaults() {
+        let binds = Bindings::from_text("# mine\n\nundo  F5  alt+z\n", Keymap::Qwerty).unwrap();
+        assert_eq!(binds.action(chord("F5")), Some(Action::Undo));
+        assert_eq!(binds.action(chord("alt+z")), Some(Action::Undo));
+        assert_eq!(binds.action(chord("ctrl+z")), None);
+        assert_eq!(binds.action(chord("ctrl+y")), Some(Action::Redo));
+
+        // A line with nothing after the action just unbinds it
+        let binds = Bindings::from_text("hint", Keymap::Qwerty).unwrap();
+        assert_eq!(binds.action(chord("?")), None);
+    }
+
+    #[test]
+    fn clashes_are_reported_by_line() {
+        assert_eq!(error("teleport F5"), "line 1: no action called \"teleport\"");
+        assert_eq!(error("\nundo ctrl+"), "line 2: can't read key \"ctrl+\"");
+        assert_eq!(error("undo q"), "line 1: q is taken by a card or claim key");
+        assert_eq!(error("undo 3"), "line 1: 3 is taken by a card or claim key");
+        assert_eq!(error("undo F5\nredo F5"), "line 2: F5 is already bound to undo on line 1");
+        assert_eq!(error("undo Esc"), "line 1: Esc is already bound to deselect by default");
+        assert_eq!(error("quit"), "nothing left to quit with");
+
+        // Taking a key from an action that's been rebound is fine
+        assert!(Bindings::from_text("deselect F2\nundo Esc", Keymap::Qwerty).is_ok());
+
+        // Modified card keys aren't card keys
+        assert!(Bindings::from_text("undo ctrl+q", Keymap::Qwerty).is_ok());
+    }
+
+    #[test]
+    fn cursor_keys_are_bound_apart() {
+        // l is the leaderboard until the cursor's out, and h is a card on QWERTY
+        let binds = Bindings::defaults(Keymap::Qwerty);
+        assert_eq!(binds.action(chord("l")), Some(Action::Leaderboard));
+        assert_eq!(binds.cursor_dir(chord("l")), Some(Dir::Right));
+        assert_eq!(binds.cursor_dir(chord("h")), Some(Dir::Left));
+        assert_eq!(binds.action(chord("h")), None);
+
+        // Only keys that are just for the cursor repeat
+        let mut held = binds.repeatable(Keymap::Qwerty);
+        held.sort_by_key(|c| c.to_string());
+        let held: Vec<String> = held.iter().map(|c| c.to_string()).collect();
+        assert_eq!(held, ["Down", "Left", "Right", "Up", "j", "k"]);
+
+        // The same goes for bindings files, but not for claim keys or within the cursor's keys
+        let binds = Bindings::from_text("left q Left\nright Esc Right", Keymap::Qwerty).unwrap();
+        assert_eq!(binds.cursor_dir(chord("q")), Some(Dir::Left));
+        assert_eq!(binds.cursor_dir(chord("Esc")), Some(Dir::Right));
+        assert_eq!(binds.action(chord("Esc")), Some(Action::DeselectAll));
+        assert_eq!(error("left 2"), "line 1: 2 is taken by a card or claim key");
+        assert_eq!(error("left x\nright x"), "line 2: x is already bound to left on line 1");
+        assert_eq!(error("left j"), "line 1: j is already bound to down by default");
+    }
+
+    #[test]
+    fn hints_follow_the_bindings() {
+        let hints = [(Action::Leaderboard, "leaderboard"), (Action::Quit, "quit")];
+        assert_eq!(Bindings::defaults(Keymap::Qwerty).hints(&hints), "l: leaderboard    Backspace: quit");
+
+        let binds = Bindings::from_text("leaderboard\nquit ctrl+q", Keymap::Qwerty).unwrap();
+        assert_eq!(binds.hints(&hints), "ctrl+q: quit");
+    }
+
+    #[test]
+    fn defaults_skip_card_keys() {
+        let binds = Bindings::defaults(Keymap::Dvorak);
+        assert_eq!(binds.action(chord("u")), None);
+        assert_eq!(Bindings::defaults(Keymap::Qwerty).action(chord("u")), Some(Action::Undo));
+    }
+}
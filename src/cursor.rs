@@ -0,0 +1,159 @@
+use crate::game::GameState;
+use crate::pos::DealtPos;
+
+// The focus cursor, for playing without a mouse: a position on the board that the arrow keys (or
+// hjkl) move from card to card, and that Space or Enter picks. It's hidden until it's first moved,
+// and again after Esc or a click.
+//
+// Moves skip over empty cells, and wrap around at the edges of the board. The extra columns are
+// only ever stepped into when there are cards in them, so most of the time a row wraps after its
+// fourth card.
+
+const ROWS: u8 = 3;
+const COLS: u8 = 6;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Dir {
+    Up,
+    Down,
+    Left,
+    Right
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Cursor {
+    pos: Option<DealtPos>
+}
+
+impl Cursor {
+    pub fn pos(&self) -> Option<DealtPos> {
+        self.pos
+    }
+
+    // Moves to the next card over in dir, if there is one. The first move only shows the
+    // cursor, on the card nearest the top left.
+    pub fn step(&mut self, state: &GameState, dir: Dir) {
+        let from = match self.pos {
+            Some(pos) => pos,
+            None => {
+                self.pos = nearest(state, DealtPos::new(0, 0));
+                return;
+            }
+        };
+
+        let (row, col) = (from.row(), from.col());
+        let next = (1..line_len(dir)).map(|i| match dir {
+            Dir::Right => DealtPos::new(row, (col + i) % COLS),
+            Dir::Left => DealtPos::new(row, (col + COLS - i) % COLS),
+            Dir::Down => DealtPos::new((row + i) % ROWS, col),
+            Dir::Up => DealtPos::new((row + ROWS - i) % ROWS, col)
+        }).find(|&p| state.card_at(p).is_some());
+
+        if let Some(pos) = next { self.pos = Some(pos); };
+    }
+
+    pub fn hide(&mut self) {
+        self.pos = None;
+    }
+
+    // Keeps the cursor on a card once the board has changed under it, by moving it to the
+    // nearest one if its own cell was emptied. Hidden cursors stay hidden.
+    pub fn settle(&mut self, state: &GameState) {
+        if let Some(pos) = self.pos {
+            if state.card_at(pos).is_none() {
+                self.pos = nearest(state, pos);
+            };
+        };
+    }
+}
+
+fn line_len(dir: Dir) -> u8 {
+    match dir {
+        Dir::Left | Dir::Right => COLS,
+        Dir::Up | Dir::Down => ROWS
+    }
+}
+
+// Closest dealt card to pos, by rows plus columns, with ties going to whichever comes first
+// reading across the board
+fn nearest(state: &GameState, pos: DealtPos) -> Option<DealtPos> {
+    (0..ROWS)
+        .flat_map(|row| (0..COLS).map(move |col| DealtPos::new(row, col)))
+        .filter(|&p| state.card_at(p).is_some())
+        .min_by_key(|p| p.row().abs_diff(pos.row()) + p.col().abs_diff(pos.col()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::GameOptions;
+
+    // A board with cards at just these spots
+    fn board(spots: &[(u8, u8)]) -> GameState {
+        let saved = GameState::with_seed(GameOptions::default(), 1).save();
+        let mut pool: Vec<_> = saved.deck.iter().copied().chain(saved.dealt.iter().map(|&(_, c)| c)).collect();
+        let dealt = spots.iter().map(|&(row, col)| (DealtPos::new(row, col), pool.pop().unwrap())).collect();
+        let saved = crate::game::SavedGame { deck: pool, dealt, ..saved };
+        GameState::resume(GameOptions::default(), saved).unwrap()
+    }
+
+    fn full(cols: u8) -> Vec<(u8, u8)> {
+        (0..ROWS).flat_map(|row| (0..cols).map(move |col| (row, col))).collect()
+    }
+
+    fn walk(state: &GameState, from: (u8, u8), dir: Dir, steps: usize) -> Vec<(u8, u8)> {
+        let mut cursor = Cursor { pos: Some(DealtPos::new(from.0, from.1)) };
+        (0..steps).map(|_| {
+            cursor.step(state, dir);
+            let pos = cursor.pos().unwrap();
+            (pos.row(), pos.col())
+        }).collect()
+    }
+
+    #[test]
+    fn first_step_only_shows_it() {
+        // Ties go to whichever comes first reading across
+        let state = board(&[(1, 0), (0, 1), (2, 3)]);
+        let mut cursor = Cursor::default();
+        cursor.step(&state, Dir::Right);
+        assert_eq!(cursor.pos(), Some(DealtPos::new(0, 1)));
+
+        cursor.hide();
+        assert_eq!(cursor.pos(), None);
+    }
+
+    #[test]
+    fn wraps_at_the_edges() {
+        let state = board(&full(4));
+        assert_eq!(walk(&state, (0, 2), Dir::Right, 3), vec![(0, 3), (0, 0), (0, 1)]);
+        assert_eq!(walk(&state, (0, 0), Dir::Left, 2), vec![(0, 3), (0, 2)]);
+        assert_eq!(walk(&state, (1, 1), Dir::Down, 3), vec![(2, 1), (0, 1), (1, 1)]);
+        assert_eq!(walk(&state, (0, 3), Dir::Up, 2), vec![(2, 3), (1, 3)]);
+    }
+
+    #[test]
+    fn skips_empty_cells() {
+        // A found set that the empty deck couldn't replace, and a partly filled extra column
+        let mut spots = full(5);
+        spots.retain(|&s| s != (0, 1) && s != (1, 1) && s != (2, 4));
+        let state = board(&spots);
+
+        assert_eq!(walk(&state, (0, 0), Dir::Right, 5), vec![(0, 2), (0, 3), (0, 4), (0, 0), (0, 2)]);
+        assert_eq!(walk(&state, (2, 3), Dir::Right, 2), vec![(2, 0), (2, 1)]);
+        assert_eq!(walk(&state, (2, 1), Dir::Down, 2), vec![(2, 1), (2, 1)]);
+        assert_eq!(walk(&state, (1, 4), Dir::Down, 2), vec![(0, 4), (1, 4)]);
+        assert_eq!(walk(&state, (1, 0), Dir::Left, 2), vec![(1, 4), (1, 3)]);
+    }
+
+    #[test]
+    fn settles_on_the_nearest_card() {
+        let state = board(&[(0, 0), (2, 3)]);
+        let mut cursor = Cursor { pos: Some(DealtPos::new(1, 3)) };
+        cursor.settle(&state);
+        assert_eq!(cursor.pos(), Some(DealtPos::new(2, 3)));
+
+        let mut cursor = Cursor::default();
+        cursor.settle(&state);
+        assert_eq!(cursor.pos(), None);
+    }
+}
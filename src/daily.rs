@@ -0,0 +1,201 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use log::warn;
+
+use crate::bindings::{Action, Bindings};
+use crate::game::{GameOptions, GameState};
+use crate::stats::date;
+use crate::store::{Line, Store};
+use crate::util::*;
+
+// The daily challenge: one deal per day, the same for everyone, on standard rules. Each day can
+// only be played once, so a run is written to daily.tsv, as given up, before its deal is shown.
+// It's updated to the result once the game finishes or the player quits, and a day that's
+// already in there can't be started again, even if russet never got the chance to update it.
+
+const HEADER: &str = "day\tseed\tfinished\ttime_ms\tpenalty_ms\tsets\tbad_sets\thints\tscore";
+
+// Days since the unix epoch, in UTC so everyone changes over at the same moment
+pub fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() / 86400)
+}
+
+// Deck seed for a day. This is splitmix64, written out rather than using std's hasher, since that
+// isn't guaranteed to stay the same between Rust versions and every copy of russet has to agree.
+// The deck it seeds is shuffled by deck::SeededRng, which is pinned for the same reason.
+pub fn seed(day: u64) -> u64 {
+    let mut z = day.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// Everyone plays on the defaults, whatever else was asked for
+pub fn options(day: u64) -> GameOptions {
+    GameOptions {
+        daily: Some(day),
+        ..Default::default()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Run {
+    pub day: u64,
+    pub seed: u64,
+
+    // false if the player quit before the game was over
+    pub finished: bool,
+    pub elapsed: Duration,
+    pub penalty: Duration,
+    pub sets_found: u32,
+    pub bad_sets: u32,
+    pub hints: u32,
+    pub score: i32
+}
+
+impl Run {
+    // A run that's only just started, which counts as giving up until it's updated
+    pub fn started(day: u64) -> Self {
+        Self {
+            day,
+            seed: seed(day),
+            finished: false,
+            elapsed: Duration::ZERO,
+            penalty: Duration::ZERO,
+            sets_found: 0,
+            bad_sets: 0,
+            hints: 0,
+            score: 0
+        }
+    }
+
+    pub fn of(state: &GameState, day: u64) -> Self {
+        let stats = state.stats();
+        Self {
+            day,
+            seed: stats.seed,
+            finished: state.is_over(),
+            elapsed: stats.elapsed,
+            penalty: stats.penalty,
+            sets_found: stats.sets_found,
+            bad_sets: stats.bad_sets,
+            hints: stats.hints,
+            score: stats.score
+        }
+    }
+
+    // One line to paste into chat, e.g. "russet daily 2026-10-18: 07:42, 25 sets, 1 miss"
+    pub fn result(&self) -> String {
+        let time = fmt_duration(self.elapsed + self.penalty);
+        let misses = if self.bad_sets == 1 { "miss" } else { "misses" };
+        let time = if self.finished { time } else { format!("gave up at {}", time) };
+
+        format!("russet daily {}: {}, {} sets, {} {}",
+            date(self.day * 86400), time, self.sets_found, self.bad_sets, misses)
+    }
+}
+
+impl Line for Run {
+    fn to_line(&self) -> String {
+        format!("{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            self.day, self.seed, u8::from(self.finished),
+            self.elapsed.as_millis(), self.penalty.as_millis(),
+            self.sets_found, self.bad_sets, self.hints, self.score)
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 9 { return None; };
+
+        Some(Self {
+            day: fields[0].parse().ok()?,
+            seed: fields[1].parse().ok()?,
+            finished: fields[2] != "0",
+            elapsed: Duration::from_millis(fields[3].parse().ok()?),
+            penalty: Duration::from_millis(fields[4].parse().ok()?),
+            sets_found: fields[5].parse().ok()?,
+            bad_sets: fields[6].parse().ok()?,
+            hints: fields[7].parse().ok()?,
+            score: fields[8].parse().ok()?
+        })
+    }
+}
+
+fn store() -> Option<Store<Run>> {
+    Store::open("daily.tsv", HEADER)
+}
+
+// The run for day from the default store, if there is one. Errors are passed on rather than
+// treated as not played, otherwise an unreadable file would be enough to get another go.
+pub fn played(day: u64) -> Result<Option<Run>> {
+    match store() {
+        Some(store) => Ok(store.load()?.into_iter().find(|r| r.day == day)),
+        None => Ok(None)
+    }
+}
+
+// Claims day before its deal is shown, so there's no second go to be had by killing russet
+// partway through. As with played, errors are passed on rather than letting the day be replayed.
+pub fn start(day: u64) -> Result<()> {
+    match store() {
+        Some(store) => store.replace(&Run::started(day), |r| r.day == day),
+        None => Ok(())
+    }
+}
+
+// Updates the run for state's daily challenge to how it went. Does nothing for any other kind
+// of game.
+pub fn record_run(state: &GameState) -> Option<Run> {
+    let run = Run::of(state, state.options().daily?);
+
+    match store() {
+        Some(store) => if let Err(err) = store.replace(&run, |r| r.day == run.day) {
+            warn!("failed to save daily run: {:?}", err);
+        },
+        None => warn!("no data directory, daily run not saved")
+    }
+
+    Some(run)
+}
+
+// Shown in place of the usual game over overlay
+pub fn overlay(run: &Run, binds: &Bindings) -> Vec<String> {
+    vec![
+        String::from("DAILY CHALLENGE"),
+        String::new(),
+        run.result(),
+        String::new(),
+        format!("Score: {}    Hints: {}", run.score, run.hints),
+        String::from("Come back tomorrow for a new deal!"),
+        String::new(),
+        binds.hints(&[(Action::NewGame, "play a regular game"), (Action::Leaderboard, "leaderboard"), (Action::Quit, "quit")]),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deck::Deck;
+
+    // Every copy of russet has to deal the same board on the same day
+    #[test]
+    fn a_day_always_deals_the_same() {
+        let day = 20000;
+        assert_eq!(seed(day), 16693281570183516702);
+        assert_ne!(seed(day), seed(day + 1));
+
+        let deck = Deck::with_seed(options(day).variant, seed(day));
+        let top: Vec<String> = deck.cards().iter().rev().take(3).map(|c| c.code()).collect();
+        assert_eq!(top, ["0222", "1121", "2120"]);
+    }
+
+    // A run that never got updated still has to stop the day being played again
+    #[test]
+    fn a_started_run_counts_as_given_up() {
+        let run = Run::parse(&Run::started(20000).to_line()).unwrap();
+        assert_eq!(run, Run::started(20000));
+        assert_eq!(run.result(), "russet daily 2024-10-04: gave up at 00:00, 0 sets, 0 misses");
+    }
+}
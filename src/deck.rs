@@ -1,4 +1,5 @@
-use rand::seq::SliceRandom;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 use std::fmt::{Display, Formatter, Error};
 use std::string::ToString;
 
@@ -6,7 +7,8 @@ use std::string::ToString;
 pub enum CardColor {
     Color1, 
     Color2,
-    Color3
+    Color3,
+    Color4
 }
 
 impl Display for CardColor {
@@ -14,7 +16,8 @@ impl Display for CardColor {
         match self {
             Self::Color1 => write!(f, "Color1"),
             Self::Color2 => write!(f, "Color2"),
-            Self::Color3 => write!(f, "Color3")
+            Self::Color3 => write!(f, "Color3"),
+            Self::Color4 => write!(f, "Color4")
         }
     }
 }
@@ -23,7 +26,8 @@ impl Display for CardColor {
 pub enum CardShape {
     Oval,
     Diamond,
-    Squiggle
+    Squiggle,
+    Hourglass
 }
 
 impl Display for CardShape {
@@ -31,14 +35,15 @@ impl Display for CardShape {
         match self {
             Self::Oval => write!(f, "Oval"),
             Self::Diamond => write!(f, "Diamond"),
-            Self::Squiggle => write!(f, "Squiggle")
+            Self::Squiggle => write!(f, "Squiggle"),
+            Self::Hourglass => write!(f, "Hourglass")
         }
     }
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Debug, Hash)]
 pub enum CardNumber {
-    One=1, Two=2, Three=3
+    One=1, Two=2, Three=3, Four=4
 }
 
 impl Display for CardNumber {
@@ -46,7 +51,8 @@ impl Display for CardNumber {
         match self {
             Self::One => write!(f, "One"),
             Self::Two => write!(f, "Two"),
-            Self::Three => write!(f, "Three")
+            Self::Three => write!(f, "Three"),
+            Self::Four => write!(f, "Four")
         }
     }
 }
@@ -56,14 +62,15 @@ impl From<CardNumber> for i16 {
         match cn {
             CardNumber::One => 1,
             CardNumber::Two => 2,
-            CardNumber::Three => 3
+            CardNumber::Three => 3,
+            CardNumber::Four => 4
         }
     }
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Debug, Hash)]
 pub enum CardFill {
-    Solid, Striped, Empty
+    Solid, Striped, Empty, Dotted
 }
 
 impl Display for CardFill {
@@ -71,11 +78,47 @@ impl Display for CardFill {
         match self {
             Self::Solid => write!(f, "Solid"),
             Self::Striped => write!(f, "Striped"),
-            Self::Empty => write!(f, "Empty")
+            Self::Empty => write!(f, "Empty"),
+            Self::Dotted => write!(f, "Dotted")
         }
     }
 }
 
+// Every card has exactly this many attributes. Variants with fewer just pin the rest to their
+// first value.
+pub const ATTRIBUTES: usize = 4;
+pub const MAX_VALUES: usize = 4;
+
+// Lets the set rule, the solver and deck generation treat all four attributes the same way,
+// as indices 0..MAX_VALUES
+pub trait Attribute: Copy + Eq {
+    const ALL: [Self; MAX_VALUES];
+
+    fn index(self) -> usize {
+        Self::ALL.iter().position(|&x| x == self).unwrap()
+    }
+
+    fn from_index(i: usize) -> Self {
+        Self::ALL[i]
+    }
+}
+
+impl Attribute for CardColor {
+    const ALL: [Self; MAX_VALUES] = [Self::Color1, Self::Color2, Self::Color3, Self::Color4];
+}
+
+impl Attribute for CardShape {
+    const ALL: [Self; MAX_VALUES] = [Self::Oval, Self::Diamond, Self::Squiggle, Self::Hourglass];
+}
+
+impl Attribute for CardNumber {
+    const ALL: [Self; MAX_VALUES] = [Self::One, Self::Two, Self::Three, Self::Four];
+}
+
+impl Attribute for CardFill {
+    const ALL: [Self; MAX_VALUES] = [Self::Solid, Self::Striped, Self::Empty, Self::Dotted];
+}
+
 #[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Debug, Hash)]
 pub struct Card {
     pub color: CardColor,
@@ -84,6 +127,99 @@ pub struct Card {
     pub fill: CardFill
 }
 
+impl Card {
+    // Attribute values as indices, in the order color, shape, number, fill
+    pub fn attrs(self) -> [usize; ATTRIBUTES] {
+        [self.color.index(), self.shape.index(), self.number.index(), self.fill.index()]
+    }
+
+    // Short form for files: one digit per attribute, in the same order as attrs
+    pub fn code(self) -> String {
+        self.attrs().iter().map(|a| a.to_string()).collect()
+    }
+
+    pub fn from_code(s: &str) -> Option<Self> {
+        let digits: Vec<usize> = s.chars()
+            .map(|ch| ch.to_digit(10).and_then(|d| usize::try_from(d).ok()).filter(|&d| d < MAX_VALUES))
+            .collect::<Option<_>>()?;
+
+        digits.try_into().ok().map(Self::from_attrs)
+    }
+
+    pub fn from_attrs([c, s, n, f]: [usize; ATTRIBUTES]) -> Self {
+        Self {
+            color: CardColor::from_index(c),
+            shape: CardShape::from_index(s),
+            number: CardNumber::from_index(n),
+            fill: CardFill::from_index(f)
+        }
+    }
+}
+
+// What the player is looking for
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Goal {
+    // `values` cards that, for every attribute, are either all the same or all different
+    Set,
+
+    // Four cards that split into two pairs, where both pairs would be completed into a set by
+    // the same card. That card doesn't have to be anywhere on the board. Needs three values.
+    SuperSet
+}
+
+// Which cards are in play, and what counts as a set. The first `attributes` attributes (in
+// color, shape, number, fill order) each take `values` different values, and the rest are fixed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Variant {
+    pub attributes: u8,
+    pub values: u8,
+    pub goal: Goal
+}
+
+impl Variant {
+    pub const STANDARD: Self = Self { attributes: 4, values: 3, goal: Goal::Set };
+
+    // 27 cards, every card is solid
+    pub const JUNIOR: Self = Self { attributes: 3, values: 3, goal: Goal::Set };
+
+    // 256 cards, sets of four
+    pub const QUAD: Self = Self { attributes: 4, values: 4, goal: Goal::Set };
+
+    // Standard deck, SuperSET rules
+    pub const SUPER: Self = Self { attributes: 4, values: 3, goal: Goal::SuperSet };
+
+    // Number of cards the player has to select
+    pub fn set_size(self) -> usize {
+        match self.goal {
+            Goal::Set => usize::from(self.values),
+            Goal::SuperSet => 4
+        }
+    }
+
+    pub fn deck_size(self) -> usize {
+        usize::from(self.values).pow(u32::from(self.attributes))
+    }
+}
+
+impl Display for Variant {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        match *self {
+            Self::STANDARD => write!(f, "standard"),
+            Self::JUNIOR => write!(f, "junior"),
+            Self::QUAD => write!(f, "quad"),
+            Self::SUPER => write!(f, "super"),
+            Self { attributes, values, goal: Goal::Set } => write!(f, "{}x{}", attributes, values),
+            Self { attributes, values, goal: Goal::SuperSet } => write!(f, "{}x{} super", attributes, values)
+        }
+    }
+}
+
+impl Default for Variant {
+    fn default() -> Self {
+        Self::STANDARD
+    }
+}
+
 impl Display for Card {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
         write!(f, "Card{{")?;
@@ -97,35 +233,86 @@ impl Display for Card {
 
 #[derive(Clone, Debug)]
 pub struct Deck {
-    cards: Vec<Card>
-}
+    cards: Vec<Card>,
+    variant: Variant,
 
-pub fn all_cards() -> Vec<Card> {
-    let colors = [CardColor::Color1, CardColor::Color2, CardColor::Color3];
-    let shapes = [CardShape::Oval, CardShape::Diamond, CardShape::Squiggle];
-    let numbers = [CardNumber::One, CardNumber::Two, CardNumber::Three];
-    let fills = [CardFill::Solid, CardFill::Striped, CardFill::Empty];
+    // Shuffling is a pure function of this, so it's enough to reproduce the whole deck
+    seed: u64
+}
 
-    let mut cards = Vec::with_capacity(81);
+// Every card in the variant, in a fixed order. Count through 0..deck_size in base `values`, and
+// each digit is one attribute.
+pub fn all_cards(variant: Variant) -> Vec<Card> {
+    let values = usize::from(variant.values);
+    let mut cards = Vec::with_capacity(variant.deck_size());
 
-    for c in colors.iter() {
-        for s in shapes.iter() {
-            for n in numbers.iter() {
-                for f in fills.iter() {
-                    cards.push(Card{color:*c, shape:*s, number:*n, fill:*f});
-                }
-            }
+    for mut n in 0..variant.deck_size() {
+        let mut attrs = [0; ATTRIBUTES];
+        for a in attrs.iter_mut().take(usize::from(variant.attributes)) {
+            *a = n % values;
+            n /= values;
         }
+        cards.push(Card::from_attrs(attrs));
     }
 
     cards
 }
 
+// Randomness that a seed pins down completely, for anything that has to come out the same from
+// the same seed on every copy of russet. Neither StdRng nor rand's shuffling promise that across
+// rand versions, so this is a fixed algorithm, keyed directly from the seed, and does its own
+// picking rather than going through gen_range.
+pub struct SeededRng(ChaCha8Rng);
+
+impl SeededRng {
+    pub fn new(seed: u64) -> Self {
+        let mut key = [0; 32];
+        key[..8].copy_from_slice(&seed.to_le_bytes());
+        Self(ChaCha8Rng::from_seed(key))
+    }
+
+    // Uniformly in 0..n. Draws that fall in the uneven part at the top of the range are thrown
+    // back, so every value is equally likely.
+    pub fn below(&mut self, n: usize) -> usize {
+        let n = n as u64;
+        let zone = u64::MAX - u64::MAX % n;
+        loop {
+            let x = self.0.next_u64();
+            if x < zone { return (x % n) as usize; };
+        }
+    }
+}
+
 impl Deck {
-    pub fn new() -> Deck {
-        let mut cards = all_cards();
-        cards.shuffle(&mut rand::thread_rng());
-        Deck{cards}
+    pub fn new(variant: Variant) -> Deck {
+        Self::with_seed(variant, rand::random())
+    }
+
+    pub fn with_seed(variant: Variant, seed: u64) -> Deck {
+        let mut cards = all_cards(variant);
+        let mut rng = SeededRng::new(seed);
+        for i in (1..cards.len()).rev() {
+            cards.swap(i, rng.below(i + 1));
+        }
+        Deck{cards, variant, seed}
+    }
+
+    // Picks a deck back up exactly as it was, e.g. from a saved game. The top of the deck is the
+    // end of cards.
+    pub fn from_parts(variant: Variant, seed: u64, cards: Vec<Card>) -> Deck {
+        Deck{cards, variant, seed}
+    }
+
+    pub fn cards(&self) -> &[Card] {
+        &self.cards
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
     }
 
     pub fn len(&self) -> usize {
@@ -136,7 +323,19 @@ impl Deck {
         self.cards.pop()
     }
 
-    fn is_empty(&self) -> bool {
+    // Moves card to the top of the deck, so it's the next one popped.
+    // Returns false (and leaves the deck alone) if the card isn't in the deck at all.
+    pub fn promote(&mut self, card: Card) -> bool {
+        if let Some(i) = self.cards.iter().position(|&c| c == card) {
+            let c = self.cards.remove(i);
+            self.cards.push(c);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
         return self.cards.is_empty();
     }
 
@@ -158,3 +357,33 @@ impl Deck {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_deck() {
+        let deck = Deck::with_seed(Variant::STANDARD, 5);
+        assert_eq!(deck.cards(), Deck::with_seed(Variant::STANDARD, 5).cards());
+        assert_ne!(deck.cards(), Deck::with_seed(Variant::STANDARD, 6).cards());
+
+        let mut sorted = deck.cards().to_vec();
+        sorted.sort();
+        let mut all = all_cards(Variant::STANDARD);
+        all.sort();
+        assert_eq!(sorted, all);
+    }
+
+    // If this changes, every replay and daily challenge out there deals differently
+    #[test]
+    fn seeds_deal_the_same_on_every_build() {
+        let deck = Deck::with_seed(Variant::STANDARD, 1);
+        let top: Vec<String> = deck.cards().iter().rev().take(6).map(|c| c.code()).collect();
+        assert_eq!(top, ["0200", "0101", "0120", "1120", "0122", "2000"]);
+
+        let mut rng = SeededRng::new(42);
+        let picks: Vec<usize> = (0..6).map(|_| rng.below(81)).collect();
+        assert_eq!(picks, [74, 60, 50, 51, 39, 9]);
+    }
+}
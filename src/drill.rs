@@ -0,0 +1,309 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::time::{Duration, Instant};
+
+use rand::seq::SliceRandom;
+
+use crate::bindings::{Action, Bindings};
+use crate::deck::{Card, Variant, ATTRIBUTES, all_cards};
+use crate::game::{ChangeAtom, ChangeSet};
+use crate::pos::DealtPos;
+use crate::score::Score;
+use crate::solver;
+use crate::store::{self, Line, Store};
+use crate::util::*;
+
+// Practice at spotting sets: two cards go up top, a row of candidates below, and the player picks
+// the one that completes the set. The wrong candidates are all one attribute off the right one,
+// so there's no getting by on a glance.
+//
+// Rounds are shown with the same Deal, Undeal, Select and Hint changes as a regular game, so the
+// animation thread draws and hit-tests them without knowing anything about drills.
+//
+// Every answer is appended to drill.tsv in the data directory, and the stats overlay groups them
+// by which attributes differ between the two cards, since that's what makes a set easy or hard.
+
+pub const CANDIDATES: usize = 6;
+
+// How long the right answer stays highlighted before the next round
+const FEEDBACK: Duration = Duration::from_millis(1200);
+
+const HEADER: &str = "differ\tcorrect\ttime_ms";
+const ATTRIBUTE_NAMES: [&str; ATTRIBUTES] = ["color", "shape", "number", "fill"];
+
+// Which attributes differ between the two cards of a round, one bit per attribute in the same
+// order as Card::attrs
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Combo(u8);
+
+impl Combo {
+    pub fn of(c0: Card, c1: Card) -> Self {
+        let (a0, a1) = (c0.attrs(), c1.attrs());
+        Self((0..ATTRIBUTES).filter(|&a| a0[a] != a1[a]).map(|a| 1 << a).sum())
+    }
+}
+
+impl Display for Combo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        let names: Vec<&str> = (0..ATTRIBUTES)
+            .filter(|a| self.0 & (1 << a) != 0)
+            .map(|a| ATTRIBUTE_NAMES[a])
+            .collect();
+        write!(f, "{}", names.join(", "))
+    }
+}
+
+// One answer, as kept in drill.tsv
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Attempt {
+    pub combo: Combo,
+    pub correct: bool,
+    pub took: Duration
+}
+
+impl Line for Attempt {
+    fn to_line(&self) -> String {
+        format!("{}\t{}\t{}", self.combo.0, u8::from(self.correct), self.took.as_millis())
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 3 { return None; };
+
+        Some(Self {
+            combo: Combo(fields[0].parse().ok()?),
+            correct: fields[1] != "0",
+            took: Duration::from_millis(fields[2].parse().ok()?)
+        })
+    }
+}
+
+struct Round {
+    pair: [Card; 2],
+    candidates: Vec<Card>,
+    answer: Card,
+    shown: Instant
+}
+
+impl Round {
+    fn new() -> Self {
+        let mut rng = rand::thread_rng();
+        let deck = all_cards(Variant::STANDARD);
+        let pair: Vec<Card> = deck.choose_multiple(&mut rng, 2).copied().collect();
+        let pair = [pair[0], pair[1]];
+        let answer = solver::third(pair[0], pair[1]);
+
+        // Every card that's one attribute off the answer, leaving out the pair themselves
+        let mut near: Vec<Card> = (0..ATTRIBUTES)
+            .flat_map(|a| (0..3).map(move |v| (a, v)))
+            .filter(|&(a, v)| answer.attrs()[a] != v)
+            .map(|(a, v)| {
+                let mut attrs = answer.attrs();
+                attrs[a] = v;
+                Card::from_attrs(attrs)
+            })
+            .filter(|c| !pair.contains(c))
+            .collect();
+
+        near.shuffle(&mut rng);
+        let mut candidates: Vec<Card> = near.into_iter().take(CANDIDATES - 1).collect();
+        candidates.push(answer);
+        candidates.shuffle(&mut rng);
+
+        Self { pair, candidates, answer, shown: Instant::now() }
+    }
+
+    // Pair centered in the top row, candidates along the bottom one
+    fn layout(&self) -> Vec<(Card, DealtPos)> {
+        let pair = self.pair.iter().enumerate().map(|(i, &c)| (c, DealtPos::new(0, u8::ffrom(i) + 2)));
+        let candidates = self.candidates.iter().enumerate().map(|(i, &c)| (c, DealtPos::new(2, u8::ffrom(i))));
+        pair.chain(candidates).collect()
+    }
+
+    fn find(&self, card: Card) -> Option<DealtPos> {
+        self.layout().into_iter().find(|&(c, _)| c == card).map(|(_, p)| p)
+    }
+}
+
+pub struct Drill {
+    round: Round,
+
+    // Set once the current round is answered, until the next one goes up
+    answered: Option<Instant>,
+
+    // Everything from drill.tsv, plus this session
+    attempts: Vec<Attempt>,
+    stamp: u32
+}
+
+impl Drill {
+    pub fn new(history: Vec<Attempt>) -> Self {
+        Self { round: Round::new(), answered: None, attempts: history, stamp: 0 }
+    }
+
+    fn changeset(&mut self, changes: HashSet<ChangeAtom>) -> ChangeSet {
+        self.stamp += 1;
+        ChangeSet::new(changes, self.stamp, Score::default())
+    }
+
+    // Puts the current round on screen
+    pub fn start(&mut self) -> Vec<ChangeSet> {
+        let deals = self.round.layout().into_iter().map(|(c, p)| ChangeAtom::Deal(c, p)).collect();
+        vec![self.changeset(deals)]
+    }
+
+    // Answers the round with card. Shows the pick as selected and the right answer as hinted,
+    // then leaves them up for a moment, see due. Clicks on anything but a candidate, or after the
+    // round's been answered, are ignored.
+    pub fn pick(&mut self, card: Card) -> Option<(Attempt, Vec<ChangeSet>)> {
+        if self.answered.is_some() || !self.round.candidates.contains(&card) {
+            return None;
+        };
+
+        let attempt = Attempt {
+            combo: Combo::of(self.round.pair[0], self.round.pair[1]),
+            correct: card == self.round.answer,
+            took: self.round.shown.elapsed()
+        };
+        self.attempts.push(attempt);
+        self.answered = Some(Instant::now());
+
+        let mut chs = HashSet::new();
+        chs.insert(ChangeAtom::Select(card, self.round.find(card)?));
+        chs.insert(ChangeAtom::Hint(self.round.answer, self.round.find(self.round.answer)?));
+        Some((attempt, vec![self.changeset(chs)]))
+    }
+
+    // Once an answer has been up for long enough, swaps in the next round. Cards are taken down
+    // in a ChangeSet of their own, since the next round might deal some of them again.
+    pub fn due(&mut self) -> Vec<ChangeSet> {
+        match self.answered {
+            Some(when) if when.elapsed() >= FEEDBACK => (),
+            _ => return vec![]
+        };
+
+        let undeals = self.round.layout().into_iter().map(|(c, p)| ChangeAtom::Undeal(c, p)).collect();
+        let undeals = self.changeset(undeals);
+
+        self.round = Round::new();
+        self.answered = None;
+
+        let mut ret = vec![undeals];
+        ret.extend(self.start());
+        ret
+    }
+
+    // One line for the bottom of the screen
+    pub fn status(&self, binds: &Bindings) -> String {
+        let right = self.attempts.iter().filter(|a| a.correct).count();
+        let mut line = format!("right {}/{}", right, self.attempts.len());
+        if let Some(last) = self.attempts.last() {
+            line.push_str(&format!("    last {:.1}s", last.took.as_secs_f64()));
+        };
+        line.push_str("    pick the card that completes the set    ");
+        line.push_str(&binds.hints(&[(Action::Leaderboard, "stats"), (Action::Quit, "quit")]));
+        line
+    }
+
+    // Lines for the stats overlay: accuracy and average time for each combination of differing
+    // attributes, fewest differences first
+    pub fn summary(&self, binds: &Bindings) -> Vec<String> {
+        let mut by_combo: BTreeMap<(u32, Combo), Vec<Attempt>> = BTreeMap::new();
+        for &a in &self.attempts {
+            by_combo.entry((a.combo.0.count_ones(), a.combo)).or_default().push(a);
+        }
+
+        let mut lines = vec![String::from("DRILL STATS"), String::new()];
+        if by_combo.is_empty() {
+            lines.push(String::from("No answers yet"));
+        } else {
+            lines.push(format!("{:<28}  {:>5}  {:>5}  {:>8}", "differ in", "tries", "right", "avg time"));
+            for ((_, combo), attempts) in &by_combo {
+                lines.push(row(&combo.to_string(), attempts));
+            }
+            lines.push(String::new());
+            lines.push(row("all", &self.attempts));
+        }
+
+        lines.push(String::new());
+        lines.push(binds.hints(&[(Action::Leaderboard, "close")]));
+        lines
+    }
+}
+
+fn row(name: &str, attempts: &[Attempt]) -> String {
+    let tries = u32::ffrom(attempts.len());
+    let right = attempts.iter().filter(|a| a.correct).count();
+    let total: Duration = attempts.iter().map(|a| a.took).sum();
+    format!("{:<28}  {:>5}  {:>4}%  {:>7.1}s",
+        name, tries, right * 100 / attempts.len(), (total / tries).as_secs_f64())
+}
+
+fn store() -> Option<Store<Attempt>> {
+    Store::open("drill.tsv", HEADER)
+}
+
+// Every answer so far from the default store
+pub fn history() -> Vec<Attempt> {
+    store::load_or_warn(store(), "drill history")
+}
+
+pub fn record(attempt: Attempt) {
+    store::append_or_warn(store(), &attempt, "drill answer");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card(code: &str) -> Card {
+        Card::from_code(code).unwrap()
+    }
+
+    // How many attributes two cards differ in
+    fn distance(c0: Card, c1: Card) -> usize {
+        (0..ATTRIBUTES).filter(|&a| c0.attrs()[a] != c1.attrs()[a]).count()
+    }
+
+    #[test]
+    fn only_the_answer_completes_the_pair() {
+        // Rounds are random, so try enough of them to run into the edge cases
+        for _ in 0..500 {
+            let round = Round::new();
+            let distinct: HashSet<Card> = round.candidates.iter().copied().collect();
+            assert_eq!(round.candidates.len(), CANDIDATES);
+            assert_eq!(distinct.len(), CANDIDATES);
+            assert!(!round.candidates.iter().any(|c| round.pair.contains(c)));
+
+            let sets: Vec<Card> = round.candidates.iter().copied()
+                .filter(|&c| solver::is_valid(&[round.pair[0], round.pair[1], c], Variant::STANDARD))
+                .collect();
+            assert_eq!(sets, [round.answer]);
+            for &c in round.candidates.iter().filter(|&&c| c != round.answer) {
+                assert_eq!(distance(c, round.answer), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn combos_follow_attribute_order() {
+        assert_eq!(Combo::of(card("0000"), card("0000")), Combo(0));
+        assert_eq!(Combo::of(card("0000"), card("1000")), Combo(0b0001));
+        assert_eq!(Combo::of(card("0000"), card("0100")), Combo(0b0010));
+        assert_eq!(Combo::of(card("0000"), card("0020")), Combo(0b0100));
+        assert_eq!(Combo::of(card("0000"), card("0001")), Combo(0b1000));
+        assert_eq!(Combo::of(card("0120"), card("2100")), Combo(0b0101));
+
+        assert_eq!(Combo(0b0001).to_string(), "color");
+        assert_eq!(Combo(0b1010).to_string(), "shape, fill");
+        assert_eq!(Combo(0b1111).to_string(), "color, shape, number, fill");
+    }
+
+    #[test]
+    fn attempts_round_trip() {
+        let attempt = Attempt { combo: Combo(0b0110), correct: false, took: Duration::from_millis(2345) };
+        assert_eq!(Attempt::parse(&attempt.to_line()), Some(attempt));
+        assert_eq!(Attempt::parse("6\t1"), None);
+        assert_eq!(Attempt::parse("six\t1\t2345"), None);
+    }
+}
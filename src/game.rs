@@ -1,39 +1,27 @@
 use crate::pos::*;
-use crate::deck::{Card, Deck};
+use crate::deck::{Card, Deck, Variant, ATTRIBUTES, all_cards};
 use crate::layout::*;
+use crate::solver;
+use crate::score::{PlayerScore, Score, ScoringRules};
 use std::collections::HashSet;
+use std::time::{Duration, Instant};
+use log::info;
 use crate::util::*;
 
 use std::ops::{Index, IndexMut};
 use rand::seq::SliceRandom as _;
 
-fn all_diff_or_all_same<T: Eq> (a:T, b:T, c:T) -> bool {
-    ((a == b) && (b == c) && (a == c)) || 
-    ((a != b) && (b != c) && (a != c))
-}
-
-fn is_a_set(c0:Card, c1:Card, c2:Card) -> bool {
-    all_diff_or_all_same(c0.color, c1.color, c2.color) &&
-    all_diff_or_all_same(c0.shape, c1.shape, c2.shape) && 
-    all_diff_or_all_same(c0.number, c1.number, c2.number) &&
-    all_diff_or_all_same(c0.fill, c1.fill, c2.fill)
-}
-
-fn find_set(lay:Layout) -> Option<[DealtPos; 3]> {
-    let cards:Vec<_> = lay.enumerate_2d().filter(|(_, c)| *c != None).collect();
-    for i in 0..cards.len() {
-        for j in i..cards.len() {
-            for k in j..cards.len() {
-                let (pos0, c0) = cards[i];
-                let (pos1, c1) = cards[j];
-                let (pos2, c2) = cards[k];
-                return if is_a_set(c0.unwrap(), c1.unwrap(), c2.unwrap()) {
-                    Some([pos0, pos1, pos2])
-                } else { None }
-            }
-        }
-    }
-    None
+fn all_diff_or_all_same<T: Eq>(vals: &[T]) -> bool {
+    vals.iter().all(|v| *v == vals[0]) ||
+    vals.iter().enumerate().all(|(i, v)| !vals[..i].contains(v))
+}
+
+// Doesn't check the number of cards, that's up to the variant being played
+pub fn is_a_set(cards: &[Card]) -> bool {
+    (0..ATTRIBUTES).all(|a| {
+        let vals: Vec<usize> = cards.iter().map(|c| c.attrs()[a]).collect();
+        all_diff_or_all_same(&vals)
+    })
 }
 
 // Can extend Card definition to include UID later, if necessary.
@@ -55,74 +43,520 @@ pub enum ChangeAtom {
     Deselect(Card, DealtPos),
     Fade(Card, DealtPos),
     Deal(Card, DealtPos),
+
+    // Inverses of Deal and GoodMove, for undo. Undeal sends the card back to the deck, Unfind
+    // brings a found card back to the board.
+    Undeal(Card, DealtPos),
+    Unfind(Card, DealtPos),
+
+    // Highlights a card as part of a hint, and removes that highlight again. Independent of
+    // selection, a hinted card can be selected or not.
+    Hint(Card, DealtPos),
+    Unhint(Card, DealtPos),
+
+    // SuperSET only: shows and hides the card that both pairs of the last SuperSET found
+    // complete to, next to the last-found area. It usually isn't on the board at all.
+    Link(Card),
+    Unlink(Card),
+
+    // A fresh game, of this variant, has been dealt from this seed
+    NewGame(u64, Variant),
+
+    // Deck is empty and nothing left on the board makes a set
+    GameOver(GameStats),
+
+    // Where the game stands after this ChangeSet, for the HUD. Sent along with every change.
+    Stats(GameStats),
+
+    // Puts a card straight into the last-found area, without it coming from the board first.
+    // For resuming a saved game.
+    Found(Card, GamePos),
+
+    // Puzzle only: found sets stay on the board and get listed in a side panel instead. Listed
+    // puts a card in slot slot of the set'th row of the panel, Unlisted takes it back out.
+    // As (card, set, slot).
+    Listed(Card, u8, u8),
+    Unlisted(Card, u8, u8),
+
+    // Puzzle only: the selected cards make a set, but it's the set'th one already found, so
+    // nothing happens besides the card being deselected. As (card, position, set).
+    AlreadyFound(Card, DealtPos, u8),
+
+    // Multiplayer only: a player has the board to themselves for this long, to pick out a set.
+    // Unclaim is when their turn's over, whether they found one, missed, or ran out of time.
+    Claim(u8, Duration),
+    Unclaim(u8),
+
+    // Multiplayer only: where a player stands after this ChangeSet, sent whenever it changes
+    Player(u8, PlayerScore),
+
+    // The clock has stopped, and the board should be hidden until it starts again
+    Pause,
+    Unpause,
 }
 
-// stamp is handy for identifying which came later in a more concrete way than instants
-#[derive(Clone, Debug)]
+// Summary of a game so far. Sent along with GameOver, so it reflects the end of the game there.
+#[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub struct GameStats {
+    pub seed: u64,
+    pub sets_found: u32,
+    pub cards_left: u32,
+    pub score: i32,
+    pub bad_sets: u32,
+    pub elapsed: Duration,
+
+    // Number of hinted cards, and the time penalty they've added up to
+    pub hints: u32,
+    pub penalty: Duration,
+
+    // Puzzle only: how many sets there are to find in all, 0 for any other game
+    pub sets_to_find: u32
+}
+
+// stamp is handy for identifying which came later in a more concrete way than instants.
+// score is the score once these changes have happened, so the UI can tell when it moves.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ChangeSet {
     pub changes: HashSet<ChangeAtom>,
-    pub stamp: u32
+    pub stamp: u32,
+    pub score: Score
 }
 
 impl ChangeSet {
-    pub fn new(changes: HashSet<ChangeAtom>, stamp: u32) -> Self {
-        Self { changes, stamp}
+    pub fn new(changes: HashSet<ChangeAtom>, stamp: u32, score: Score) -> Self {
+        Self { changes, stamp, score }
     }
 }
 
 impl Default for ChangeSet {
     fn default() -> Self {
-        Self { changes: HashSet::new(), stamp: 0}
+        Self { changes: HashSet::new(), stamp: 0, score: Score::default() }
+    }
+}
+
+// Settings fixed for the lifetime of a game
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct GameOptions {
+    // Reorder the deck on every deal so the main section always has a set, where possible.
+    // Turn off for classic rules, where you sometimes have to add cards.
+    pub guarantee_sets: bool,
+
+    // Added to the player's time for every card revealed by a hint
+    pub hint_penalty: Duration,
+
+    pub variant: Variant,
+    pub scoring: ScoringRules,
+
+    // The day (counting from the unix epoch) whose daily challenge this is, if it is one.
+    // See daily.rs.
+    pub daily: Option<u64>,
+
+    // Makes the game a puzzle: a fixed board with this many sets on it, which all have to be
+    // found. Nothing is ever dealt after the first twelve cards. See Layout::puzzle.
+    pub puzzle: Option<u8>,
+
+    // Hot-seat players sharing the keyboard, each claiming the board before picking a set.
+    // 1 is a regular game.
+    pub players: u8,
+
+    // How long a claim lasts before it runs out and counts as a miss
+    pub claim_time: Duration
+}
+
+impl GameOptions {
+    // Short name for the kind of game these options make, e.g. for grouping high scores
+    pub fn mode(&self) -> String {
+        let mode = if self.daily.is_some() {
+            String::from("daily")
+        } else if let Some(n) = self.puzzle {
+            format!("{} puzzle {}", self.variant, n)
+        } else if self.guarantee_sets {
+            self.variant.to_string()
+        } else {
+            format!("{} classic", self.variant)
+        };
+
+        if self.players > 1 { format!("{} {}p", mode, self.players) } else { mode }
+    }
+
+    pub fn multiplayer(&self) -> bool {
+        self.players > 1
+    }
+}
+
+impl Default for GameOptions {
+    fn default() -> Self {
+        Self {
+            guarantee_sets: true,
+            hint_penalty: Duration::from_secs(10),
+            variant: Variant::STANDARD,
+            scoring: ScoringRules::default(),
+            daily: None,
+            puzzle: None,
+            players: 1,
+            claim_time: Duration::from_secs(5)
+        }
     }
 }
 
+// Everything needed to pick a game back up in a later session. Undo history and the current hint
+// are left behind. See save.rs for how this is written to disk.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SavedGame {
+    pub variant: Variant,
+    pub guarantee_sets: bool,
+    pub puzzle: Option<u8>,
+    pub seed: u64,
+
+    // In deck order, so the last card is the next one dealt
+    pub deck: Vec<Card>,
+    pub dealt: Vec<(DealtPos, Card)>,
+    pub last_set_found: Option<Vec<Card>>,
+    pub selects: Vec<Card>,
+
+    // Puzzle only, the sets found so far
+    pub found: Vec<Vec<Card>>,
+
+    pub elapsed: Duration,
+    pub penalty: Duration,
+    pub sets_found: u32,
+    pub hints: u32,
+    pub bad_sets: u32,
+    pub score: Score,
+    pub log: Vec<(Duration, Command)>
+}
+
+// The parts of GameState that undo/redo roll back and forth.
+// Hints and the game clock deliberately aren't here, undoing doesn't refund a hint.
+#[derive(Clone, Debug)]
+struct Snapshot {
+    deck: Deck,
+    layout: Layout,
+    last_set_found: Option<Vec<Card>>,
+    selects: Vec<Card>,
+    sets_found: u32,
+    found: Vec<Vec<Card>>,
+
+    // Only the points earned from sets get rolled back, penalties stick
+    score: Score,
+
+    // last_found, counting from the start of the game, so pauses in between don't matter.
+    // Without it, undoing a set would refund its speed bonus but leave the bonus clock restarted,
+    // and taking the same set again would be worth nearly the whole bonus.
+    last_found: Duration
+}
+
+// One undoable step. board holds just the atoms that moved cards around (GoodMove, Reflow,
+// Deal), selection changes are worked out from the snapshots instead.
+#[derive(Clone, Debug)]
+struct HistoryEntry {
+    before: Snapshot,
+    after: Snapshot,
+    board: Vec<ChangeAtom>
+}
+
 #[derive(Clone, Debug)]
 pub struct GameState {
     deck: Deck,
     layout: Layout,
-    last_set_found: Option<(Card, Card, Card)>,
+    options: GameOptions,
+    last_set_found: Option<Vec<Card>>,
     selects: Vec<Card>,
     changesets: Vec<ChangeSet>,
-    id_counter: u32
+    id_counter: u32,
+
+    // The set currently being hinted at, and how many of its cards have been revealed
+    hint: Option<(Vec<Card>, usize)>,
+
+    undos: Vec<HistoryEntry>,
+    redos: Vec<HistoryEntry>,
+
+    started: Instant,
+    ended: Option<Instant>,
+    sets_found: u32,
+    hints: u32,
+    penalty: Duration,
+    bad_sets: u32,
+
+    score: Score,
+
+    // When the last set was found (or the game started), for the speed bonus
+    last_found: Instant,
+
+    // Every command that changed the game, and when, counting from the start. Together with the
+    // seed and options, enough to play the whole game back.
+    log: Vec<(Duration, Command)>,
+
+    // Puzzle only: every set found so far, in the order they were found, each sorted so the same
+    // set always compares equal
+    found: Vec<Vec<Card>>,
+
+    // Multiplayer only: one entry per player, and whoever has the board claimed right now,
+    // since when
+    players: Vec<PlayerScore>,
+    claim: Option<(u8, Instant)>,
+
+    // Since when the game's been paused, if it is
+    paused: Option<Instant>
+}
+
+// Everything a player (or anything else driving the game) can ask of a GameState.
+// All of these go through GameState::apply.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    // Toggles selection on a dealt card. Selecting the last card of a set checks it.
+    Select(Card),
+    DeselectAll,
+    Hint,
+    AddCards,
+    Undo,
+    Redo,
+
+    // Multiplayer only. Claim takes the board for a player (numbered from 0), Timeout ends a
+    // claim that's run out. The game doesn't check the time itself, so that replays come out
+    // the same however fast they're played.
+    Claim(u8),
+    Timeout,
+
+    // Stops the clock, or starts it again. Nothing else goes through while it's stopped.
+    Pause,
+
+    NewGame,
+    Quit
 }
 
-// GameState update should take one of these instead of just layoutPos
-// pub enum UserInteraction {
-//    SelectCard,
-//    FindSet,
-//    NewGame
-// }
+// What happened as a result of a command
+#[derive(Clone, Debug)]
+pub enum Outcome {
+    // Game carries on, with these changes to show (possibly none)
+    Changes(Vec<ChangeSet>),
+
+    // Game was replaced by a new one, so anything on screen is stale.
+    // These are the changes for the new game's first deal.
+    Restarted(Vec<ChangeSet>),
+    Quit
+}
+
+// Reasons a command can be rejected. A rejected command never changes the game.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CommandError {
+    // Only NewGame and Quit make sense once the game is over
+    GameOver,
+
+    // And NewGame makes sense only then
+    NotOver,
+    NotDealt(Card),
+    BoardFull,
+    DeckEmpty,
+    NoSets,
+    HintExhausted,
+    NothingToUndo,
+    NothingToRedo,
+
+    // Multiplayer only
+    NoSuchPlayer(u8),
+    Claimed,
+    NotClaimed,
+
+    // Hints and undo would make it too easy to take a turn off someone else
+    NotInMultiplayer,
+
+    // Only Pause and Quit go through while the game is paused
+    Paused
+}
 
 impl Default for GameState {
     fn default() -> Self {
-        let mut deck = Deck::new();
-        let mut cards = [[None; 6]; 3];
-        let mut changesets = Vec::new();
+        Self::new(GameOptions::default())
+    }
+}
+
+impl GameState {
+    pub fn new(options: GameOptions) -> Self {
+        Self::with_seed(options, rand::random())
+    }
+
+    /// same seed and options always produce the same game
+    pub fn with_seed(options: GameOptions, seed: u64) -> Self {
+        info!("starting game with seed {}", seed);
+
+        // Gets its own changeset, so the new game is set up before anything is dealt into it
         let mut cs = HashSet::new();
+        cs.insert(ChangeAtom::NewGame(seed, options.variant));
 
-        for (row_i, row) in (&mut cards).into_iter().enumerate() {
-            for (col_i, cel) in row.into_iter().enumerate() {
-                if col_i <= 3 {
-                    *cel = deck.pop();
-                    cs.insert(ChangeAtom::Deal(cel.unwrap(), DealtPos::new(row_i.finto(), col_i.finto())));
-                }
+        let mut state = GameState {
+            deck: Deck::with_seed(options.variant, seed),
+            layout: Layout::new([[None; 6]; 3]),
+            options,
+            last_set_found: None,
+            selects: Vec::new(),
+            changesets: vec![ChangeSet::new(cs, 0, Score::default())],
+            id_counter: 1,
+            hint: None,
+            undos: Vec::new(),
+            redos: Vec::new(),
+            started: Instant::now(),
+            ended: None,
+            sets_found: 0,
+            hints: 0,
+            penalty: Duration::ZERO,
+            bad_sets: 0,
+            score: Score::default(),
+            last_found: Instant::now(),
+            log: Vec::new(),
+            found: Vec::new(),
+            players: if options.multiplayer() { vec![PlayerScore::default(); options.players.into()] } else { vec![] },
+            claim: None,
+            paused: None
+        };
+
+        let deals = match options.puzzle {
+            Some(n) => {
+                let (deals, sets) = state.layout.puzzle(&mut state.deck, n.into());
+
+                // Only happens for counts some variants can't (or can hardly ever) make. The
+                // board is what counts from here on, see puzzle_sets.
+                if sets != usize::from(n) {
+                    info!("no puzzle board with {} sets found, playing one with {}", n, sets);
+                };
+                deals
+            },
+            None => state.layout.refill(&mut state.deck, options.guarantee_sets)
+        };
+
+        let mut cs: HashSet<ChangeAtom> = deals.into_iter().map(|(c, l)| ChangeAtom::Deal(c, l)).collect();
+        for (i, &p) in state.players.iter().enumerate() {
+            cs.insert(ChangeAtom::Player(u8::ffrom(i), p));
+        }
+        state.record(cs);
+        state
+    }
+
+    /// picks up a saved game where it left off. Options other than the variant, guarantee_sets
+    /// and puzzle come from options rather than the save. The board comes back as Deal changes,
+    /// same as a fresh game.
+    pub fn resume(options: GameOptions, saved: SavedGame) -> Result<Self> {
+        // Multiplayer games are never saved
+        let options = GameOptions {
+            variant: saved.variant,
+            guarantee_sets: saved.guarantee_sets,
+            puzzle: saved.puzzle,
+            players: 1,
+            ..options
+        };
+
+        let mut layout = Layout::new([[None; 6]; 3]);
+        for &(p, c) in &saved.dealt {
+            if layout[p].replace(c).is_some() {
+                return Err(SetError::new(SetErrorKind::BadSave, "two cards dealt to one spot"));
+            };
+        }
+
+        // Every card has to be in exactly one place, and selections have to be on the board
+        let found = saved.last_set_found.clone().unwrap_or_default();
+        let mut all: Vec<Card> = saved.deck.iter()
+            .chain(saved.dealt.iter().map(|(_, c)| c))
+            .chain(found.iter())
+            .copied()
+            .collect();
+        all.sort();
+        all.dedup();
+        let total = saved.deck.len() + saved.dealt.len() + found.len();
+        let all_dealt = saved.selects.iter().all(|c| saved.dealt.iter().any(|(_, d)| d == c));
+        let in_deck = all.iter().all(|c| all_cards(options.variant).contains(c));
+        if all.len() != total || !in_deck || !all_dealt || saved.selects.len() >= options.variant.set_size() {
+            return Err(SetError::new(SetErrorKind::BadSave, "cards don't add up"));
+        };
+
+        // Puzzle sets never leave the board
+        let sets_ok = saved.found.iter().all(|set|
+            solver::is_valid(set, options.variant)
+            && set.iter().all(|c| saved.dealt.iter().any(|(_, d)| d == c)));
+        if !sets_ok {
+            return Err(SetError::new(SetErrorKind::BadSave, "found sets don't add up"));
+        };
+
+        let mut cs = HashSet::new();
+        cs.insert(ChangeAtom::NewGame(saved.seed, options.variant));
+
+        // Can't be in the future, but might be further back than this machine's been up
+        let now = Instant::now();
+        let started = now.checked_sub(saved.elapsed).unwrap_or(now);
+
+        let mut state = GameState {
+            deck: Deck::from_parts(options.variant, saved.seed, saved.deck),
+            layout,
+            options,
+            last_set_found: saved.last_set_found,
+            selects: saved.selects,
+            changesets: vec![ChangeSet::new(cs, 0, Score::default())],
+            id_counter: 1,
+            hint: None,
+            undos: Vec::new(),
+            redos: Vec::new(),
+            started,
+            ended: None,
+            sets_found: saved.sets_found,
+            hints: saved.hints,
+            penalty: saved.penalty,
+            bad_sets: saved.bad_sets,
+            score: saved.score,
+            last_found: now,
+            log: saved.log,
+            found: saved.found,
+            players: Vec::new(),
+            claim: None,
+            paused: None
+        };
+
+        let deals = saved.dealt.iter().map(|&(p, c)| ChangeAtom::Deal(c, p)).collect();
+        state.record(deals);
+
+        // Selections need the cards on screen first, so they get a ChangeSet of their own
+        let mut chs: HashSet<ChangeAtom> = state.selects.iter()
+            .map(|&c| ChangeAtom::Select(c, state.find(c)))
+            .collect();
+
+        let of = u8::ffrom(found.len());
+        for (slot, &c) in found.iter().enumerate() {
+            chs.insert(ChangeAtom::Found(c, GamePos::LastFound { slot: u8::ffrom(slot), of }));
+        }
+        state.relink(None, &mut chs);
+
+        for (i, set) in state.found.iter().enumerate() {
+            for (slot, &c) in set.iter().enumerate() {
+                chs.insert(ChangeAtom::Listed(c, u8::ffrom(i), u8::ffrom(slot)));
             }
         }
 
-        changesets.push(ChangeSet::new(cs, 0));
+        state.record(chs);
+        state.check_over();
+        Ok(state)
+    }
 
-        GameState {
-            deck,
-            layout: Layout::new(cards),
-            last_set_found: None,
-            selects: Vec::new(),
-            changesets,
-            id_counter: 1
+    pub fn save(&self) -> SavedGame {
+        let stats = self.stats();
+
+        SavedGame {
+            variant: self.variant(),
+            guarantee_sets: self.options.guarantee_sets,
+            puzzle: self.options.puzzle,
+            seed: self.seed(),
+            deck: self.deck.cards().to_vec(),
+            dealt: self.enumerate_cards().filter_map(|(p, c)| c.map(|c| (p, c))).collect(),
+            last_set_found: self.last_set_found.clone(),
+            selects: self.selects.clone(),
+            found: self.found.clone(),
+            elapsed: stats.elapsed,
+            penalty: self.penalty,
+            sets_found: self.sets_found,
+            hints: self.hints,
+            bad_sets: self.bad_sets,
+            score: self.score,
+            log: self.log.clone()
         }
     }
-}
 
-impl GameState {
     pub fn changes(&mut self) -> Vec<ChangeSet> {
         std::mem::take(&mut self.changesets)
     }
@@ -131,15 +565,67 @@ impl GameState {
         !self.changesets.is_empty()
     }
 
+    /// applies a single command to the game. Changes it produced are returned directly, rather
+    /// than waiting to be picked up by changes().
+    pub fn apply(&mut self, cmd: Command) -> std::result::Result<Outcome, CommandError> {
+        if self.is_over() && !matches!(cmd, Command::NewGame | Command::Quit) {
+            return Err(CommandError::GameOver);
+        };
+
+        if self.paused.is_some() && !matches!(cmd, Command::Pause | Command::Quit) {
+            return Err(CommandError::Paused);
+        };
+
+        if self.options.multiplayer() && matches!(cmd, Command::Hint | Command::Undo | Command::Redo) {
+            return Err(CommandError::NotInMultiplayer);
+        };
+
+        match cmd {
+            Command::Select(card) => self.select(card)?,
+            Command::DeselectAll => self.deselect_all()?,
+            Command::Hint => self.hint()?,
+            Command::AddCards => self.add_cards()?,
+            Command::Undo => self.undo()?,
+            Command::Redo => self.redo()?,
+            Command::Claim(player) => self.claim(player)?,
+            Command::Timeout => self.timeout()?,
+            // Pausing leaves no trace in the log, see toggle_pause
+            Command::Pause => {
+                self.toggle_pause();
+                return Ok(Outcome::Changes(self.changes()));
+            },
+            // There's only one daily challenge a day, so playing again means a regular game
+            Command::NewGame => {
+                if !self.is_over() { return Err(CommandError::NotOver); };
+                *self = Self::new(GameOptions { daily: None, ..self.options });
+                return Ok(Outcome::Restarted(self.changes()));
+            },
+            Command::Quit => return Ok(Outcome::Quit)
+        };
+
+        self.log.push((self.started.elapsed(), cmd));
+        Ok(Outcome::Changes(self.changes()))
+    }
+
+    // Every method that changes the board records exactly one ChangeSet for it
+    fn record(&mut self, mut chs: HashSet<ChangeAtom>) {
+        chs.insert(ChangeAtom::Stats(self.stats()));
+        self.changesets.push(ChangeSet::new(chs, self.id_counter, self.score));
+        self.id_counter += 1;
+    }
+
     /// given a user's selection of cards, adjusts game state and self.changes to indicate
     /// necessary next steps
-    pub fn select(&mut self, card: Card) {
+    fn select(&mut self, card: Card) -> std::result::Result<(), CommandError> {
+        if self.position(card).is_none() {
+            return Err(CommandError::NotDealt(card));
+        };
 
-        debug_assert!(
-            self.enumerate_cards()
-                .find(|(_, c)| c.is_some() && c.unwrap() == card)
-                .is_some());
+        if self.options.multiplayer() && self.claim.is_none() {
+            return Err(CommandError::NotClaimed);
+        };
 
+        let before = self.snapshot();
         let mut chs = HashSet::new();
 
         if self.selects.contains(&card) {
@@ -147,64 +633,536 @@ impl GameState {
             chs.insert(ChangeAtom::Deselect(card, self.find(card)));
         }
 
-        else if self.selects.len() <= 1 {
+        else if self.selects.len() + 1 < self.set_size() {
             self.selects.push(card);
             chs.insert(ChangeAtom::Select(card, self.find(card)));
         }
 
-        else if self.selects.len() == 2 {
+        else if self.selects.len() + 1 == self.set_size() {
             self.selects.push(card);
 
-            let (c0, c1, c2) = (
-                self.selects.pop().unwrap(),
-                self.selects.pop().unwrap(),
-                self.selects.pop().unwrap()
-            );
+            let cards = std::mem::take(&mut self.selects);
+            let positions: Vec<DealtPos> = cards.iter().map(|&c| self.find(c)).collect();
 
-            let (p0, p1, p2) = (
-                self.find(c0),
-                self.find(c1),
-                self.find(c2),
-            );
+            if self.options.puzzle.is_some() && solver::is_valid(&cards, self.variant()) {
+                self.list_set(cards, &mut chs);
 
-            if is_a_set(c0, c1, c2) {
+            } else if solver::is_valid(&cards, self.variant()) {
+                let old_link = self.link();
+                let of = u8::ffrom(cards.len());
 
-                for p in [p0, p1, p2] { self.layout.remove(p); };
-                self.selects.clear();
-                self.last_set_found = Some((c0, c1, c2));
+                // SuperSETs go in pair by pair, so the last-found area shows how they fit together
+                let (cards, positions) = match solver::super_pairs(&cards) {
+                    Some((order, _)) => (
+                        order.iter().map(|&i| cards[i]).collect(),
+                        order.iter().map(|&i| positions[i]).collect()
+                    ),
+                    None => (cards, positions)
+                };
+
+                for &p in &positions { self.layout.remove(p); };
 
-                chs.insert(ChangeAtom::GoodMove(c0, p0, GamePos::LastFound0));
-                chs.insert(ChangeAtom::GoodMove(c1, p1, GamePos::LastFound1));
-                chs.insert(ChangeAtom::GoodMove(c2, p2, GamePos::LastFound2));
+                for (slot, (&c, &p)) in cards.iter().zip(&positions).enumerate() {
+                    let slot = u8::ffrom(slot);
+                    chs.insert(ChangeAtom::GoodMove(c, p, GamePos::LastFound { slot, of }));
+                }
+
+                self.last_set_found = Some(cards);
+                self.relink(old_link, &mut chs);
 
                 for (c, l0, l1) in self.layout.redistribute() {
                     chs.insert(ChangeAtom::Reflow(c, l0, l1));
                 };
 
-                for (c, l) in self.layout.refill(&mut self.deck) {
+                for (c, l) in self.layout.refill(&mut self.deck, self.options.guarantee_sets) {
                     chs.insert(ChangeAtom::Deal(c, l));
                 };
 
+                self.sets_found += 1;
+                self.clear_hint(&mut chs);
+                self.score_set();
+
             } else {
-                self.selects.clear();
-                chs.insert(ChangeAtom::BadOutline(c0, p0));
-                chs.insert(ChangeAtom::BadOutline(c1, p1));
-                chs.insert(ChangeAtom::BadOutline(c2, p2));
+                for (&c, &p) in cards.iter().zip(&positions) {
+                    chs.insert(ChangeAtom::BadOutline(c, p));
+                }
+
+                self.score_miss();
             }
+
+            // Right or wrong, that was the claimer's one go
+            self.unclaim(&mut chs);
         } else {
-            panic!("self.selects should never have more than 3 elements");
+            unreachable!("self.selects should never hold a full set");
         }
 
-        self.changesets.push(ChangeSet::new(chs, self.id_counter));
-        self.id_counter += 1;
+        self.remember(before, &chs);
+        self.record(chs);
+        self.check_over();
+        Ok(())
+    }
+
+    // Puzzle version of taking a set: the cards stay where they are, and the set goes on the
+    // found list instead, unless it's on there already
+    fn list_set(&mut self, mut cards: Vec<Card>, chs: &mut HashSet<ChangeAtom>) {
+        cards.sort();
+
+        if let Some(i) = self.found.iter().position(|set| *set == cards) {
+            for &c in &cards {
+                chs.insert(ChangeAtom::AlreadyFound(c, self.find(c), u8::ffrom(i)));
+            }
+            return;
+        };
+
+        let set = u8::ffrom(self.found.len());
+        for (slot, &c) in cards.iter().enumerate() {
+            chs.insert(ChangeAtom::Deselect(c, self.find(c)));
+            chs.insert(ChangeAtom::Listed(c, set, u8::ffrom(slot)));
+        }
+
+        self.found.push(cards);
+        self.sets_found += 1;
+        self.clear_hint(chs);
+        self.score_set();
+    }
+
+    // Points for a set just found, for the game and for whoever claimed it
+    fn score_set(&mut self) {
+        let rules = self.options.scoring;
+        let bonus = rules.bonus(self.last_found.elapsed());
+        self.score.sets += rules.set;
+        self.score.speed += bonus;
+        self.last_found = Instant::now();
+
+        if let Some(p) = self.claimer() {
+            p.sets_found += 1;
+            p.score.sets += rules.set;
+            p.score.speed += bonus;
+        };
+    }
+
+    fn score_miss(&mut self) {
+        let penalty = self.options.scoring.bad_set;
+        self.bad_sets += 1;
+        self.score.bad_sets -= penalty;
+
+        if let Some(p) = self.claimer() {
+            p.bad_sets += 1;
+            p.score.bad_sets -= penalty;
+        };
+    }
+
+    fn claimer(&mut self) -> Option<&mut PlayerScore> {
+        let (player, _) = self.claim?;
+        self.players.get_mut(usize::from(player))
+    }
+
+    // Multiplayer only: takes the board for player until they've picked a set or their time
+    // runs out, see timeout
+    fn claim(&mut self, player: u8) -> std::result::Result<(), CommandError> {
+        if usize::from(player) >= self.players.len() {
+            return Err(CommandError::NoSuchPlayer(player));
+        };
+
+        if self.claim.is_some() {
+            return Err(CommandError::Claimed);
+        };
+
+        self.claim = Some((player, Instant::now()));
+
+        let mut chs = HashSet::new();
+        chs.insert(ChangeAtom::Claim(player, self.options.claim_time));
+        self.record(chs);
+        Ok(())
+    }
+
+    // Ends the current claim as a miss, dropping whatever the claimer had selected so far
+    fn timeout(&mut self) -> std::result::Result<(), CommandError> {
+        if self.claim.is_none() {
+            return Err(CommandError::NotClaimed);
+        };
+
+        let mut chs = HashSet::new();
+        for card in std::mem::take(&mut self.selects) {
+            chs.insert(ChangeAtom::Deselect(card, self.find(card)));
+        }
+
+        self.score_miss();
+        self.unclaim(&mut chs);
+        self.record(chs);
+        Ok(())
+    }
+
+    fn unclaim(&mut self, chs: &mut HashSet<ChangeAtom>) {
+        if let Some((player, _)) = self.claim.take() {
+            chs.insert(ChangeAtom::Unclaim(player));
+            chs.insert(ChangeAtom::Player(player, self.players[usize::from(player)]));
+        };
+    }
+
+    // Whether the current claim has been held for longer than options.claim_time, meaning it's
+    // time to send a Timeout. Claims don't run out while the game is paused.
+    pub fn claim_expired(&self) -> bool {
+        self.paused.is_none()
+            && self.claim.is_some_and(|(_, since)| since.elapsed() >= self.options.claim_time)
+    }
+
+    // Unpausing moves every clock forward by however long the pause was, so the time spent
+    // paused counts for nothing: not for the game, the speed bonus, or anyone's claim.
+    fn toggle_pause(&mut self) {
+        let mut chs = HashSet::new();
+        match self.paused.take() {
+            Some(since) => {
+                let paused = since.elapsed();
+                self.started += paused;
+                self.last_found += paused;
+                if let Some((_, ref mut claimed)) = self.claim { *claimed += paused; };
+                chs.insert(ChangeAtom::Unpause);
+            },
+            None => {
+                self.paused = Some(Instant::now());
+                chs.insert(ChangeAtom::Pause);
+            }
+        };
+        self.record(chs);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.is_some()
+    }
+
+    pub fn claimed_by(&self) -> Option<u8> {
+        self.claim.map(|(player, _)| player)
+    }
+
+    pub fn players(&self) -> &[PlayerScore] {
+        &self.players
+    }
+
+    // Number of sets to find in a puzzle, 0 for any other game. Usually options.puzzle, but
+    // the board has the final say, since it never changes.
+    fn puzzle_sets(&self) -> usize {
+        match self.options.puzzle {
+            Some(_) => solver::layout_count(&self.layout, self.variant()),
+            None => 0
+        }
+    }
+
+    // Puzzle only: whether set has been found already, in any order
+    fn is_found(&self, set: &[Card]) -> bool {
+        let mut set = set.to_vec();
+        set.sort();
+        self.found.contains(&set)
+    }
+
+    // Only the claimer's selection can be cleared, and only by them
+    fn deselect_all(&mut self) -> std::result::Result<(), CommandError> {
+        if self.options.multiplayer() && self.claim.is_none() {
+            return Err(CommandError::NotClaimed);
+        };
+
+        if self.selects.is_empty() { return Ok(()); };
+
+        let before = self.snapshot();
+        let mut chs = HashSet::new();
+        for card in std::mem::take(&mut self.selects) {
+            chs.insert(ChangeAtom::Deselect(card, self.find(card)));
+        }
+        self.remember(before, &chs);
+        self.record(chs);
+        Ok(())
+    }
+
+    // The game ends once the board has no sets left on it and no more cards can be dealt, either
+    // because the deck ran out or because there's no room. The latter only really happens with
+    // bigger decks, where 18 cards can easily miss every set. Puzzles end once every set on the
+    // board has been found.
+    // Records a GameOver change the first time that happens.
+    fn check_over(&mut self) {
+        if self.is_over() { return; };
+
+        let over = match self.options.puzzle {
+            Some(_) => self.found.len() >= self.puzzle_sets(),
+            None => {
+                let stuck = self.deck.is_empty() || usize::from(self.layout.count()) + 3 > MAX_DEALT;
+                stuck && solver::layout_count(&self.layout, self.variant()) == 0
+            }
+        };
+        if !over { return; };
+
+        self.ended = Some(Instant::now());
+
+        let mut chs = HashSet::new();
+        chs.insert(ChangeAtom::GameOver(self.stats()));
+        self.record(chs);
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.ended.is_some()
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.deck.seed()
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.options.variant
+    }
+
+    // Number of cards that make up a set in this game
+    pub fn set_size(&self) -> usize {
+        self.variant().set_size()
+    }
+
+    pub fn score(&self) -> Score {
+        self.score
+    }
+
+    pub fn options(&self) -> GameOptions {
+        self.options
+    }
+
+    pub fn stats(&self) -> GameStats {
+        GameStats {
+            seed: self.seed(),
+            sets_found: self.sets_found,
+            cards_left: u32::ffrom(self.deck.len()),
+            score: self.score.total(),
+            bad_sets: self.bad_sets,
+            elapsed: self.ended.or(self.paused).unwrap_or_else(Instant::now) - self.started,
+            hints: self.hints,
+            penalty: self.penalty,
+            sets_to_find: u32::ffrom(self.puzzle_sets())
+        }
+    }
+
+    /// deals three more cards into the extra columns, as long as there's room for them and the
+    /// deck isn't empty. Asking while there's still a set on the board costs points.
+    /// In multiplayer, asking takes a claim, and uses it up: the claimer is the one who pays.
+    fn add_cards(&mut self) -> std::result::Result<(), CommandError> {
+        if self.options.multiplayer() && self.claim.is_none() {
+            return Err(CommandError::NotClaimed);
+        };
+
+        self.can_add_cards()?;
+
+        let wrong = solver::layout_count(&self.layout, self.variant()) > 0;
+        let before = self.snapshot();
+        let mut chs = HashSet::new();
+
+        for (c, l) in self.layout.extra3(&mut self.deck, self.options.guarantee_sets) {
+            chs.insert(ChangeAtom::Deal(c, l));
+        };
+
+        if chs.is_empty() {
+            return Err(CommandError::BoardFull);
+        };
+
+        if wrong {
+            let penalty = self.options.scoring.bad_claim;
+            self.score.bad_claims -= penalty;
+            if let Some(p) = self.claimer() {
+                p.score.bad_claims -= penalty;
+            };
+        };
+        self.unclaim(&mut chs);
+
+        self.remember(before, &chs);
+        self.record(chs);
+        self.check_over();
+        Ok(())
+    }
+
+    /// whether AddCards would deal anything right now, and if not, why not. Lets a server check
+    /// before claiming the board for a player who asked.
+    pub fn can_add_cards(&self) -> std::result::Result<(), CommandError> {
+        // A puzzle board is full by definition
+        if self.options.puzzle.is_some() {
+            return Err(CommandError::BoardFull);
+        };
+
+        if self.deck.is_empty() {
+            return Err(CommandError::DeckEmpty);
+        };
+
+        let room = self.enumerate_cards().any(|(p, c)| p.col() >= 4 && c.is_none());
+        if !room || usize::from(self.layout.count()) + 3 > MAX_DEALT {
+            return Err(CommandError::BoardFull);
+        };
+
+        Ok(())
+    }
+
+    /// reveals one more card of some set on the board, up to the whole set. Every card revealed
+    /// adds options.hint_penalty to the player's stats. The current selection isn't touched.
+    /// Puzzles only hint at sets that haven't been found yet.
+    fn hint(&mut self) -> std::result::Result<(), CommandError> {
+        if self.hint.is_none() {
+            self.hint = solver::layout_sets(&self.layout, self.variant())
+                .into_iter()
+                .map(|ps| ps.into_iter().map(|p| self.layout[p].unwrap()).collect::<Vec<Card>>())
+                .find(|set| !self.is_found(set))
+                .map(|set| (set, 0));
+        };
+
+        let card = match self.hint {
+            Some((ref set, ref mut shown)) if *shown < set.len() => {
+                *shown += 1;
+                set[*shown - 1]
+            },
+            Some(_) => return Err(CommandError::HintExhausted),
+            None => return Err(CommandError::NoSets)
+        };
+
+        self.hints += 1;
+        self.penalty += self.options.hint_penalty;
+        self.score.hints -= self.options.scoring.hint;
+
+        let mut chs = HashSet::new();
+        chs.insert(ChangeAtom::Hint(card, self.find(card)));
+        self.record(chs);
+        Ok(())
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            deck: self.deck.clone(),
+            layout: self.layout,
+            last_set_found: self.last_set_found.clone(),
+            selects: self.selects.clone(),
+            sets_found: self.sets_found,
+            found: self.found.clone(),
+            score: self.score,
+            last_found: self.last_found - self.started
+        }
+    }
+
+    // Pushes an undoable step, made up of the state before it and the changes it produced.
+    // Anything new invalidates whatever could have been redone.
+    fn remember(&mut self, before: Snapshot, chs: &HashSet<ChangeAtom>) {
+        let board = chs.iter()
+            .copied()
+            .filter(|a| matches!(a,
+                ChangeAtom::GoodMove(..) | ChangeAtom::Reflow(..) | ChangeAtom::Deal(..)))
+            .collect();
+
+        self.undos.push(HistoryEntry { before, after: self.snapshot(), board });
+        self.redos.clear();
+    }
+
+    // Swaps in snap, recording changes to get from the current board to it. board is the list of
+    // card movements to show, already pointing in the right direction.
+    fn restore(&mut self, snap: Snapshot, board: impl Iterator<Item=ChangeAtom>) {
+        let mut chs: HashSet<ChangeAtom> = board.collect();
+
+        // Selections are diffed rather than replayed. Deselects use the cards' current positions,
+        // selects their positions once snap is back in place.
+        for &c in self.selects.iter().filter(|c| !snap.selects.contains(c)) {
+            if let Some(p) = self.position(c) {
+                chs.insert(ChangeAtom::Deselect(c, p));
+            }
+        }
+
+        let old_selects = std::mem::take(&mut self.selects);
+        let old_link = self.link();
+        self.clear_hint(&mut chs);
+
+        // Same goes for the puzzle's found list. It only ever grows or shrinks at the end, so
+        // everything past the end of the shorter list is what changed.
+        for (i, set) in self.found.iter().enumerate().skip(snap.found.len()) {
+            for (slot, &c) in set.iter().enumerate() {
+                chs.insert(ChangeAtom::Unlisted(c, u8::ffrom(i), u8::ffrom(slot)));
+            }
+        }
+        for (i, set) in snap.found.iter().enumerate().skip(self.found.len()) {
+            for (slot, &c) in set.iter().enumerate() {
+                chs.insert(ChangeAtom::Listed(c, u8::ffrom(i), u8::ffrom(slot)));
+            }
+        }
+
+        let Snapshot { deck, layout, last_set_found, selects, sets_found, found, score, last_found } = snap;
+        self.deck = deck;
+        self.layout = layout;
+        self.last_set_found = last_set_found;
+        self.selects = selects;
+        self.sets_found = sets_found;
+        self.found = found;
+        self.score.sets = score.sets;
+        self.score.speed = score.speed;
+        self.last_found = self.started + last_found;
+        self.relink(old_link, &mut chs);
+
+        for &c in self.selects.iter().filter(|c| !old_selects.contains(c)) {
+            chs.insert(ChangeAtom::Select(c, self.find(c)));
+        }
+
+        self.record(chs);
+    }
+
+    fn undo(&mut self) -> std::result::Result<(), CommandError> {
+        let entry = self.undos.pop().ok_or(CommandError::NothingToUndo)?;
+
+        let inverse = entry.board.iter().map(|&a| match a {
+            ChangeAtom::GoodMove(c, p, _) => ChangeAtom::Unfind(c, p),
+            ChangeAtom::Reflow(c, p0, p1) => ChangeAtom::Reflow(c, p1, p0),
+            ChangeAtom::Deal(c, p) => ChangeAtom::Undeal(c, p),
+            _ => unreachable!("history only keeps card movements")
+        });
+
+        self.restore(entry.before.clone(), inverse);
+        self.redos.push(entry);
+        Ok(())
+    }
+
+    fn redo(&mut self) -> std::result::Result<(), CommandError> {
+        let entry = self.redos.pop().ok_or(CommandError::NothingToRedo)?;
+        self.restore(entry.after.clone(), entry.board.iter().copied());
+        self.undos.push(entry);
+        Ok(())
+    }
+
+    // Drops the current hint, un-highlighting whichever of its cards are still on the board.
+    // Called whenever a set is taken, since the hint might not exist anymore.
+    fn clear_hint(&mut self, chs: &mut HashSet<ChangeAtom>) {
+        if let Some((set, shown)) = self.hint.take() {
+            for &c in &set[..shown] {
+                if let Some(p) = self.position(c) {
+                    chs.insert(ChangeAtom::Unhint(c, p));
+                }
+            }
+        }
+    }
+
+    // Card linking the pairs of the last set found, if that was a SuperSET
+    fn link(&self) -> Option<Card> {
+        self.last_set_found
+            .as_deref()
+            .and_then(solver::super_pairs)
+            .map(|(_, link)| link)
+    }
+
+    // Swaps the shown link over to the current one. Nothing is recorded if it hasn't changed,
+    // since the order of an Unlink and Link of the same card in one ChangeSet would be ambiguous.
+    fn relink(&self, old: Option<Card>, chs: &mut HashSet<ChangeAtom>) {
+        let new = self.link();
+        if old == new { return; };
+
+        if let Some(c) = old { chs.insert(ChangeAtom::Unlink(c)); };
+        if let Some(c) = new { chs.insert(ChangeAtom::Link(c)); };
+    }
+
+    fn position(&self, card: Card) -> Option<DealtPos> {
+        self.enumerate_cards()
+            .find(|&(_, c)| c == Some(card))
+            .map(|(pos, _)| pos)
     }
 
     fn find(&self, card: Card) -> DealtPos {
-        let (pos, _) = self.enumerate_cards()
-            .filter(|(_, c)| c.is_some() && c.unwrap() == card)
-            .next()
-            .unwrap();
-        pos
+        self.position(card).unwrap()
+    }
+
+    pub fn card_at(&self, pos: DealtPos) -> Option<Card> {
+        self.layout[pos]
     }
 
     pub fn enumerate_cards(&self) -> impl Iterator<Item=(DealtPos, Option<Card>)> {
@@ -215,7 +1173,248 @@ impl GameState {
     //     self.selects.contains(&pos)
     // }
 
-    pub fn last_set_found(&self) -> Option<(Card, Card, Card)> {
-        self.last_set_found
+    pub fn log(&self) -> &[(Duration, Command)] {
+        &self.log
+    }
+
+    pub fn last_set_found(&self) -> Option<&[Card]> {
+        self.last_set_found.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Everything undo and redo are meant to put back, leaving out the clock and the log
+    fn board(gs: &GameState) -> SavedGame {
+        SavedGame { elapsed: Duration::ZERO, log: vec![], ..gs.save() }
+    }
+
+    fn some_set(gs: &GameState) -> Vec<Card> {
+        solver::find_set(&gs.layout, gs.variant()).unwrap()
+            .into_iter()
+            .map(|p| gs.card_at(p).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn undo_and_redo_a_selection() {
+        let mut gs = GameState::with_seed(GameOptions::default(), 7);
+        let start = board(&gs);
+        let card = gs.card_at(DealtPos::new(0, 0)).unwrap();
+
+        gs.apply(Command::Select(card)).unwrap();
+        let picked = board(&gs);
+        assert_eq!(picked.selects, vec![card]);
+
+        gs.apply(Command::Undo).unwrap();
+        assert_eq!(board(&gs), start);
+        gs.apply(Command::Redo).unwrap();
+        assert_eq!(board(&gs), picked);
+        assert!(gs.apply(Command::Redo).is_err());
+    }
+
+    #[test]
+    fn undo_and_redo_a_found_set() {
+        let mut gs = GameState::with_seed(GameOptions::default(), 11);
+        let start = board(&gs);
+        for card in some_set(&gs) {
+            gs.apply(Command::Select(card)).unwrap();
+        }
+        let found = board(&gs);
+        assert_eq!(found.sets_found, 1);
+        assert_ne!(found.deck, start.deck);
+
+        for _ in 0..3 { gs.apply(Command::Undo).unwrap(); }
+        assert_eq!(board(&gs), start);
+        assert!(gs.apply(Command::Undo).is_err());
+
+        for _ in 0..3 { gs.apply(Command::Redo).unwrap(); }
+        assert_eq!(board(&gs), found);
+    }
+
+    // Undoing a set and taking it again mustn't restart the clock the speed bonus runs on
+    #[test]
+    fn undo_puts_the_bonus_clock_back() {
+        let mut gs = GameState::with_seed(GameOptions::default(), 11);
+        let half = gs.options.scoring.bonus_window / 2;
+        gs.started -= half;
+        gs.last_found -= half;
+
+        let set = some_set(&gs);
+        for &card in &set { gs.apply(Command::Select(card)).unwrap(); }
+        let speed = gs.score.speed;
+        assert!(speed > 0 && speed < gs.options.scoring.speed_bonus);
+
+        for _ in 0..set.len() { gs.apply(Command::Undo).unwrap(); }
+        assert_eq!(gs.score.speed, 0);
+        for &card in &set { gs.apply(Command::Select(card)).unwrap(); }
+        assert!(gs.score.speed <= speed);
+    }
+
+    // Nobody but the claimer can touch the board, and asking for cards is charged to them
+    #[test]
+    fn multiplayer_add_and_deselect_need_the_claim() {
+        let options = GameOptions { players: 2, ..Default::default() };
+        let mut gs = GameState::with_seed(options, 5);
+        let card = gs.card_at(DealtPos::new(0, 0)).unwrap();
+
+        assert!(gs.apply(Command::AddCards).is_err());
+        assert!(gs.apply(Command::DeselectAll).is_err());
+        assert_eq!(gs.layout.count(), 12);
+
+        gs.apply(Command::Claim(1)).unwrap();
+        gs.apply(Command::Select(card)).unwrap();
+        gs.apply(Command::DeselectAll).unwrap();
+        assert!(gs.selects.is_empty());
+
+        gs.apply(Command::AddCards).unwrap();
+        assert_eq!(gs.layout.count(), 15);
+        assert_eq!(gs.claimed_by(), None);
+        let penalty = -gs.options.scoring.bad_claim;
+        assert_eq!(gs.players()[1].score.bad_claims, penalty);
+        assert_eq!(gs.players()[0].score.bad_claims, 0);
+        assert_eq!(gs.score().bad_claims, penalty);
+    }
+
+    #[test]
+    fn extra_cards_stop_at_max_dealt() {
+        let mut gs = GameState::with_seed(GameOptions::default(), 7);
+        gs.apply(Command::AddCards).unwrap();
+        gs.apply(Command::AddCards).unwrap();
+        assert_eq!(usize::from(gs.layout.count()), MAX_DEALT);
+
+        assert_eq!(gs.can_add_cards(), Err(CommandError::BoardFull));
+        assert_eq!(gs.apply(Command::AddCards).err(), Some(CommandError::BoardFull));
+    }
+
+    // A game resumed with main dealt across the main section and extra down the extra one,
+    // and deck left to deal
+    fn dealt(variant: Variant, main: &[Card], extra: &[Card], deck: Vec<Card>) -> GameState {
+        let options = GameOptions { variant, ..GameOptions::default() };
+        let spots = |cols: std::ops::Range<u8>| (0..3).flat_map(move |row| cols.clone().map(move |col| DealtPos::new(row, col)));
+        let dealt = spots(0..4).zip(main.iter().copied())
+            .chain(spots(4..6).zip(extra.iter().copied()))
+            .collect();
+        let saved = SavedGame { deck, dealt, selects: vec![], last_set_found: None, ..GameState::with_seed(options, 1).save() };
+        GameState::resume(options, saved).unwrap()
+    }
+
+    #[test]
+    fn over_once_nothing_can_be_dealt_and_no_set_is_left() {
+        // Two values per attribute can never make a set
+        let std = Variant::STANDARD;
+        let twos: Vec<Card> = (0..16).map(|i| Card::from_attrs([i & 1, i >> 1 & 1, i >> 2 & 1, i >> 3 & 1])).collect();
+        assert!(dealt(std, &twos[..12], &[], vec![]).is_over());
+        assert!(!dealt(std, &twos[..12], &[], twos[12..].to_vec()).is_over());
+
+        // Nor can three when sets take four, and a full board is as stuck as an empty deck
+        let four = Variant { values: 4, ..std };
+        let threes: Vec<Card> = (0..18).map(|i| Card::from_attrs([i % 3, i / 3 % 3, i / 9 % 3, 0])).collect();
+        let spare = vec![Card::from_attrs([3, 3, 3, 3])];
+        assert!(dealt(four, &threes[..12], &threes[12..], spare.clone()).is_over());
+        assert!(!dealt(four, &threes[..12], &threes[12..15], spare).is_over());
+    }
+
+    #[test]
+    fn finding_the_last_set_ends_the_game() {
+        // 0000, 1111 and 2222 are the only set, with 2222 out in the extra section
+        let mut main: Vec<Card> = (0..8).map(|i| Card::from_attrs([0, i & 1, i >> 1 & 1, i >> 2 & 1])).collect();
+        main.push(Card::from_attrs([1, 1, 1, 1]));
+        let last = [main[0], main[8], Card::from_attrs([2, 2, 2, 2])];
+        let mut gs = dealt(Variant::STANDARD, &main, &last[2..], vec![]);
+        assert!(!gs.is_over());
+
+        for card in last {
+            gs.apply(Command::Select(card)).unwrap();
+        }
+        assert_eq!(gs.sets_found, 1);
+        assert!(gs.is_over());
+        assert!(gs.apply(Command::Hint).is_err());
+    }
+
+    #[test]
+    fn hints_show_one_more_card_a_press() {
+        let mut gs = GameState::with_seed(GameOptions::default(), 7);
+        let (step, points) = (gs.options.hint_penalty, gs.options.scoring.hint);
+        let mut shown = vec![];
+
+        for n in 1..=3 {
+            let csets = match gs.apply(Command::Hint) {
+                Ok(Outcome::Changes(csets)) => csets,
+                _ => panic!("no hint")
+            };
+            let mut hinted = csets.iter().flat_map(|cs| cs.changes.iter())
+                .filter_map(|a| if let ChangeAtom::Hint(c, _) = a { Some(*c) } else { None });
+            let card = hinted.next().unwrap();
+            assert_eq!(hinted.next(), None);
+            assert!(!shown.contains(&card));
+            shown.push(card);
+
+            assert_eq!(gs.hints, n);
+            assert_eq!(gs.penalty, step * n);
+            assert_eq!(gs.score.hints, -points * i32::try_from(n).unwrap());
+        }
+        assert!(solver::is_valid(&shown, gs.variant()));
+
+        // The whole set's out, so there's nothing more to give away or charge for
+        assert_eq!(gs.apply(Command::Hint).err(), Some(CommandError::HintExhausted));
+        assert_eq!(gs.hints, 3);
+        assert_eq!(gs.penalty, step * 3);
+
+        // Finding it takes the hint down, and the next one starts over
+        for &card in &shown {
+            gs.apply(Command::Select(card)).unwrap();
+        }
+        assert_eq!(gs.sets_found, 1);
+        assert!(gs.hint.is_none());
+        gs.apply(Command::Hint).unwrap();
+        assert_eq!(gs.hint.as_ref().map(|&(_, shown)| shown), Some(1));
+        assert_eq!(gs.hints, 4);
+    }
+
+    #[test]
+    fn bad_commands_are_refused() {
+        let mut gs = GameState::with_seed(GameOptions::default(), 7);
+        let start = board(&gs);
+        let undealt = gs.deck.cards()[0];
+        assert_eq!(gs.apply(Command::Select(undealt)).err(), Some(CommandError::NotDealt(undealt)));
+        assert_eq!(gs.apply(Command::Undo).err(), Some(CommandError::NothingToUndo));
+        assert_eq!(gs.apply(Command::Claim(0)).err(), Some(CommandError::NoSuchPlayer(0)));
+        assert_eq!(board(&gs), start);
+
+        // Paused, only unpausing and quitting go through
+        let card = gs.card_at(DealtPos::new(0, 0)).unwrap();
+        gs.apply(Command::Pause).unwrap();
+        assert_eq!(gs.apply(Command::Select(card)).err(), Some(CommandError::Paused));
+        assert_eq!(gs.apply(Command::Hint).err(), Some(CommandError::Paused));
+        gs.apply(Command::Pause).unwrap();
+        gs.apply(Command::Select(card)).unwrap();
+
+        // Over, there's nothing left to do but start again
+        let twos: Vec<Card> = (0..12).map(|i| Card::from_attrs([i & 1, i >> 1 & 1, i >> 2 & 1, i >> 3 & 1])).collect();
+        let mut gs = dealt(Variant::STANDARD, &twos, &[], vec![]);
+        assert_eq!(gs.apply(Command::Select(twos[0])).err(), Some(CommandError::GameOver));
+        assert_eq!(gs.apply(Command::AddCards).err(), Some(CommandError::GameOver));
+        assert!(matches!(gs.apply(Command::NewGame), Ok(Outcome::Restarted(_))));
+    }
+
+    #[test]
+    fn a_running_game_cant_be_thrown_away() {
+        let mut gs = GameState::with_seed(GameOptions::default(), 3);
+        let seed = gs.stats().seed;
+        assert_eq!(gs.apply(Command::NewGame).err(), Some(CommandError::NotOver));
+        assert_eq!(gs.stats().seed, seed);
+    }
+
+    #[test]
+    fn anything_new_clears_redo() {
+        let mut gs = GameState::with_seed(GameOptions::default(), 3);
+        let [a, b] = [DealtPos::new(0, 0), DealtPos::new(0, 1)].map(|p| gs.card_at(p).unwrap());
+        gs.apply(Command::Select(a)).unwrap();
+        gs.apply(Command::Undo).unwrap();
+        gs.apply(Command::Select(b)).unwrap();
+        assert!(gs.apply(Command::Redo).is_err());
     }
 }
@@ -0,0 +1,354 @@
+use std::fmt::{self, Display, Formatter};
+use std::time::{Duration, Instant};
+
+use crossterm::event::{
+    Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+
+use crate::deck::Card;
+use crate::pos::TermPos;
+use crate::util::*;
+
+// Sits between crossterm and the game: raw events go in, and what comes out is only what the
+// player meant. Most terminals report a held key as the same press over and over, as fast as they
+// can, and without this each of those was a command. Piled up behind a slow frame, they'd go on
+// undoing or dealing well after the key was let go.
+//
+// So a press of the same chord that follows the last one too quickly to have been pressed again
+// is taken as the key being held. Held keys are dropped, except for the ones that make sense
+// held (moving the cursor), which are let through at a steady rate instead. Terminals that do
+// report repeats as such are taken at their word.
+//
+// Clicks get the same treatment: a second click on the same spot soon after the first is a
+// double-click, which is reported as such rather than as another click, so it doesn't undo the
+// first one. A right click is its own thing. Games go further and tell double-clicks by card,
+// see CardClicks.
+
+// Longest gap between two presses of a key for them to be the key being held. Terminals repeat
+// every 30ms or so, and nobody taps a key twice that fast on purpose.
+const REPEAT_GAP: Duration = Duration::from_millis(80);
+
+// How often a held key that's allowed to repeat goes through
+const REPEAT_EVERY: Duration = Duration::from_millis(100);
+
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Input {
+    Key(Chord),
+    Click(TermPos),
+    DoubleClick(TermPos),
+    RightClick(TermPos),
+
+    // The pointer moved, with or without buttons held
+    Move(TermPos),
+
+    // The terminal was resized or can't be read any more, either of which ends the game
+    Lost
+}
+
+// A key plus whichever of ctrl and alt were held. Shift only counts for keys that aren't
+// characters, since for characters it's already in which character it is.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Chord {
+    pub code: KeyCode,
+    pub mods: KeyModifiers
+}
+
+const MOD_MASK: KeyModifiers = KeyModifiers::SHIFT.union(KeyModifiers::CONTROL).union(KeyModifiers::ALT);
+
+const NAMED: [(&str, KeyCode); 15] = [
+    ("Enter", KeyCode::Enter),
+    ("Esc", KeyCode::Esc),
+    ("Backspace", KeyCode::Backspace),
+    ("Delete", KeyCode::Delete),
+    ("Insert", KeyCode::Insert),
+    ("Tab", KeyCode::Tab),
+    ("Space", KeyCode::Char(' ')),
+    ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down),
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+    ("PageUp", KeyCode::PageUp),
+    ("PageDown", KeyCode::PageDown)
+];
+
+impl Chord {
+    pub fn new(code: KeyCode, mods: KeyModifiers) -> Self {
+        let mods = mods & MOD_MASK;
+        match code {
+            KeyCode::Char(_) => Self { code, mods: mods - KeyModifiers::SHIFT },
+            _ => Self { code, mods }
+        }
+    }
+
+    pub fn of(ev: &KeyEvent) -> Self {
+        Self::new(ev.code, ev.modifiers)
+    }
+
+    // Whether this could be a claim or card key, which only go by the key itself
+    pub fn is_plain(self) -> bool {
+        !self.mods.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut mods = KeyModifiers::NONE;
+        let mut rest = s;
+        loop {
+            let lower = rest.to_ascii_lowercase();
+            let (m, len) = if lower.starts_with("ctrl+") && rest.len() > 5 {
+                (KeyModifiers::CONTROL, 5)
+            } else if lower.starts_with("alt+") && rest.len() > 4 {
+                (KeyModifiers::ALT, 4)
+            } else if lower.starts_with("shift+") && rest.len() > 6 {
+                (KeyModifiers::SHIFT, 6)
+            } else {
+                break;
+            };
+            mods |= m;
+            rest = &rest[len..];
+        }
+
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) if mods.contains(KeyModifiers::SHIFT) => KeyCode::Char(c.to_ascii_uppercase()),
+            (Some(c), None) => KeyCode::Char(c),
+            _ => match NAMED.iter().find(|(name, _)| name.eq_ignore_ascii_case(rest)) {
+                Some(&(_, code)) => code,
+                None => {
+                    let n: u8 = rest.strip_prefix(&['F', 'f'][..])?.parse().ok()?;
+                    if !(1..=12).contains(&n) { return None; };
+                    KeyCode::F(n)
+                }
+            }
+        };
+
+        Some(Self::new(code, mods))
+    }
+}
+
+impl Display for Chord {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if self.mods.contains(KeyModifiers::CONTROL) { write!(f, "ctrl+")?; };
+        if self.mods.contains(KeyModifiers::ALT) { write!(f, "alt+")?; };
+        if self.mods.contains(KeyModifiers::SHIFT) { write!(f, "shift+")?; };
+
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::F(n) => write!(f, "F{}", n),
+            code => match NAMED.iter().find(|&&(_, c)| c == code) {
+                Some((name, _)) => write!(f, "{}", name),
+                None => write!(f, "{:?}", code)
+            }
+        }
+    }
+}
+
+
+pub struct InputFilter {
+    // Chords that keep doing something while held
+    repeatable: Vec<Chord>,
+
+    // The last chord pressed, when it was last seen, and when it last went through
+    last_key: Option<(Chord, Instant, Instant)>,
+
+    // The last click that went through, when it wasn't a double-click itself
+    last_click: Option<(TermPos, Instant)>
+}
+
+impl InputFilter {
+    pub fn new(repeatable: Vec<Chord>) -> Self {
+        Self { repeatable, last_key: None, last_click: None }
+    }
+
+    // What ev means, if anything
+    pub fn next(&mut self, ev: crossterm::Result<Event>) -> Option<Input> {
+        self.next_at(ev, Instant::now())
+    }
+
+    fn next_at(&mut self, ev: crossterm::Result<Event>, now: Instant) -> Option<Input> {
+        match ev {
+            Ok(Event::Key(ev)) => self.key(ev, now),
+            Ok(Event::Mouse(MouseEvent { kind, column, row, .. })) => {
+                let pos: TermPos = (row, column).finto();
+                match kind {
+                    MouseEventKind::Down(MouseButton::Left) => Some(self.click(pos, now)),
+                    MouseEventKind::Down(MouseButton::Right) => Some(Input::RightClick(pos)),
+                    MouseEventKind::Moved | MouseEventKind::Drag(_) => Some(Input::Move(pos)),
+                    _ => None
+                }
+            },
+            Ok(Event::Resize(..)) | Err(_) => Some(Input::Lost),
+            _ => None
+        }
+    }
+
+    fn key(&mut self, ev: KeyEvent, now: Instant) -> Option<Input> {
+        let chord = Chord::of(&ev);
+        let held = match (ev.kind, self.last_key) {
+            (KeyEventKind::Release, _) => return None,
+            (KeyEventKind::Repeat, _) => true,
+            (_, Some((last, seen, _))) => last == chord && now - seen < REPEAT_GAP,
+            (_, None) => false
+        };
+
+        if !held {
+            self.last_key = Some((chord, now, now));
+            return Some(Input::Key(chord));
+        };
+
+        let passed = match self.last_key {
+            Some((last, _, passed)) if last == chord => passed,
+            _ => now
+        };
+        if self.repeatable.contains(&chord) && now - passed >= REPEAT_EVERY {
+            self.last_key = Some((chord, now, now));
+            Some(Input::Key(chord))
+        } else {
+            self.last_key = Some((chord, now, passed));
+            None
+        }
+    }
+
+    fn click(&mut self, pos: TermPos, now: Instant) -> Input {
+        match self.last_click.take() {
+            Some((last, at)) if last == pos && now - at < DOUBLE_CLICK => Input::DoubleClick(pos),
+            _ => {
+                self.last_click = Some((pos, now));
+                Input::Click(pos)
+            }
+        }
+    }
+}
+
+// Double-clicks again, by card this time. A card covers plenty of spots, and a quick second
+// click anywhere on the one just picked would put it straight back, so this is fed the cards
+// the animation thread says were hit.
+#[derive(Default)]
+pub struct CardClicks {
+    last: Option<(Card, Instant)>
+}
+
+impl CardClicks {
+    // The card to pick, unless this is the second half of a double-click
+    pub fn pick(&mut self, card: Card) -> Option<Card> {
+        self.pick_at(card, Instant::now())
+    }
+
+    fn pick_at(&mut self, card: Card, now: Instant) -> Option<Card> {
+        match self.last.take() {
+            Some((last, at)) if last == card && now - at < DOUBLE_CLICK => None,
+            _ => {
+                self.last = Some((card, now));
+                Some(card)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::KeyEvent;
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    fn press(code: KeyCode) -> crossterm::Result<Event> {
+        Ok(Event::Key(KeyEvent::new(code, KeyModifiers::NONE)))
+    }
+
+    fn click(row: u16, column: u16) -> crossterm::Result<Event> {
+        let kind = MouseEventKind::Down(MouseButton::Left);
+        Ok(Event::Mouse(MouseEvent { kind, row, column, modifiers: KeyModifiers::NONE }))
+    }
+
+    fn key(c: char) -> Option<Input> {
+        Some(Input::Key(Chord::new(KeyCode::Char(c), KeyModifiers::NONE)))
+    }
+
+    #[test]
+    fn held_keys_go_through_once() {
+        let mut filter = InputFilter::new(vec![]);
+        let start = Instant::now();
+        assert_eq!(filter.next_at(press(KeyCode::Char('u')), start), key('u'));
+
+        // Each repeat comes within REPEAT_GAP of the last, however long the key's held
+        for i in 1..=20 {
+            assert_eq!(filter.next_at(press(KeyCode::Char('u')), start + ms(30 * i)), None);
+        }
+
+        // Let go and press again, or press something else
+        assert_eq!(filter.next_at(press(KeyCode::Char('u')), start + ms(700)), key('u'));
+        assert_eq!(filter.next_at(press(KeyCode::Char('p')), start + ms(710)), key('p'));
+        assert_eq!(filter.next_at(press(KeyCode::Char('u')), start + ms(720)), key('u'));
+    }
+
+    #[test]
+    fn repeatable_keys_go_through_now_and_then() {
+        let left = Chord::new(KeyCode::Left, KeyModifiers::NONE);
+        let mut filter = InputFilter::new(vec![left]);
+        let start = Instant::now();
+
+        let passed: Vec<u64> = (0..=20)
+            .filter(|i| filter.next_at(press(KeyCode::Left), start + ms(30 * i)).is_some())
+            .map(|i| 30 * i)
+            .collect();
+        assert_eq!(passed, [0, 120, 240, 360, 480, 600]);
+    }
+
+    #[test]
+    fn releases_are_ignored() {
+        let mut filter = InputFilter::new(vec![]);
+        let start = Instant::now();
+        let release = KeyEvent::new_with_kind(KeyCode::Char('u'), KeyModifiers::NONE, KeyEventKind::Release);
+        assert_eq!(filter.next_at(Ok(Event::Key(release)), start), None);
+
+        // Terminals that say when a key repeats are believed, however slow
+        let repeat = KeyEvent::new_with_kind(KeyCode::Char('u'), KeyModifiers::NONE, KeyEventKind::Repeat);
+        assert_eq!(filter.next_at(press(KeyCode::Char('u')), start), key('u'));
+        assert_eq!(filter.next_at(Ok(Event::Key(repeat)), start + ms(500)), None);
+    }
+
+    #[test]
+    fn double_clicks_are_told_apart() {
+        let mut filter = InputFilter::new(vec![]);
+        let start = Instant::now();
+        let pos: TermPos = (3u16, 5u16).finto();
+        let other: TermPos = (3u16, 6u16).finto();
+
+        assert_eq!(filter.next_at(click(3, 5), start), Some(Input::Click(pos)));
+        assert_eq!(filter.next_at(click(3, 5), start + ms(200)), Some(Input::DoubleClick(pos)));
+
+        // A third click starts over, as does a slow one or one somewhere else
+        assert_eq!(filter.next_at(click(3, 5), start + ms(300)), Some(Input::Click(pos)));
+        assert_eq!(filter.next_at(click(3, 5), start + ms(800)), Some(Input::Click(pos)));
+        assert_eq!(filter.next_at(click(3, 6), start + ms(900)), Some(Input::Click(other)));
+    }
+
+    #[test]
+    fn double_clicks_on_a_card_go_by_card() {
+        let cards = crate::deck::all_cards(Default::default());
+        let mut clicks = CardClicks::default();
+        let start = Instant::now();
+
+        assert_eq!(clicks.pick_at(cards[0], start), Some(cards[0]));
+        assert_eq!(clicks.pick_at(cards[0], start + ms(200)), None);
+        assert_eq!(clicks.pick_at(cards[0], start + ms(300)), Some(cards[0]));
+        assert_eq!(clicks.pick_at(cards[1], start + ms(400)), Some(cards[1]));
+        assert_eq!(clicks.pick_at(cards[1], start + ms(900)), Some(cards[1]));
+    }
+
+    #[test]
+    fn chords_read_back_as_written() {
+        for key in ["u", "U", "ctrl+z", "alt+shift+F5", "Space", "Esc", "ctrl+Left"] {
+            assert_eq!(Chord::parse(key).unwrap().to_string(), key);
+        }
+        assert_eq!(Chord::parse("shift+u"), Chord::parse("U"));
+        assert_eq!(Chord::parse("CTRL+enter"), Chord::parse("ctrl+Enter"));
+        assert_eq!(Chord::parse("F13"), None);
+    }
+}
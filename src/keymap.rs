@@ -0,0 +1,105 @@
+use crate::pos::DealtPos;
+use crate::util::*;
+
+// Which key picks which card. Each map is three rows of keys, one per row of the board, read
+// left to right from the first column. The letter maps are named for the keyboard layout they sit
+// on, and all use the same physical keys: the left half of the three letter rows.
+//
+// Card keys are checked before any other key, so a map that uses one of the action keys takes it
+// over. Dvorak's home row has u in it, for one, so there u and U both pick a card, and undo and
+// redo are left on their ctrl chords (see bindings).
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Keymap {
+    #[default]
+    Qwerty,
+    Dvorak,
+    Colemak,
+    Numpad
+}
+
+impl Keymap {
+    pub const NAMES: [&'static str; 4] = ["qwerty", "dvorak", "colemak", "numpad"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "qwerty" => Some(Self::Qwerty),
+            "dvorak" => Some(Self::Dvorak),
+            "colemak" => Some(Self::Colemak),
+            "numpad" => Some(Self::Numpad),
+            _ => None
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Qwerty => "qwerty",
+            Self::Dvorak => "dvorak",
+            Self::Colemak => "colemak",
+            Self::Numpad => "numpad"
+        }
+    }
+
+    fn rows(self) -> [&'static str; 3] {
+        match self {
+            Self::Qwerty => ["qwerty", "asdfgh", "zxcvbn"],
+            Self::Dvorak => ["',.pyf", "aoeuid", ";qjkxb"],
+            Self::Colemak => ["qwfpgj", "arstdh", "zxcvbk"],
+
+            // The keypad is only four keys across, so the extra columns are mouse only
+            Self::Numpad => ["789/", "456*", "123-"]
+        }
+    }
+
+    // Where on the board key picks from, if it's one of this map's keys. Shift doesn't matter.
+    pub fn pos(self, key: char) -> Option<DealtPos> {
+        let key = key.to_ascii_lowercase();
+        self.rows().iter().enumerate().find_map(|(row, keys)| {
+            keys.chars()
+                .position(|k| k == key)
+                .map(|col| DealtPos::new(u8::ffrom(row), u8::ffrom(col)))
+        })
+    }
+
+    // The key for pos, if this map reaches it
+    pub fn key(self, pos: DealtPos) -> Option<char> {
+        self.rows()[usize::from(pos.row())].chars().nth(pos.col().into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bindings::{Action, Bindings};
+    use crate::input::Chord;
+
+    const ALL: [Keymap; 4] = [Keymap::Qwerty, Keymap::Dvorak, Keymap::Colemak, Keymap::Numpad];
+
+    #[test]
+    fn keys_and_positions_match_up() {
+        for keys in ALL {
+            assert_eq!(Keymap::from_name(keys.name()), Some(keys));
+            for row in 0..3 {
+                for col in 0..6 {
+                    let pos = DealtPos::new(row, col);
+                    if let Some(k) = keys.key(pos) {
+                        assert_eq!(keys.pos(k), Some(pos), "{} {:?}", keys.name(), k);
+                        assert_eq!(keys.pos(k.to_ascii_uppercase()), Some(pos));
+                    };
+                }
+            }
+        }
+    }
+
+    // Dvorak's card keys take u and U, which leaves undo and redo on their ctrl chords
+    #[test]
+    fn every_map_can_still_undo_and_redo() {
+        for keys in ALL {
+            let binds = Bindings::defaults(keys);
+            assert_eq!(binds.action(Chord::parse("ctrl+z").unwrap()), Some(Action::Undo));
+            assert_eq!(binds.action(Chord::parse("ctrl+y").unwrap()), Some(Action::Redo));
+        }
+        let dvorak = Bindings::defaults(Keymap::Dvorak);
+        assert_eq!(dvorak.action(Chord::parse("u").unwrap()), None);
+    }
+}
@@ -1,8 +1,19 @@
 use crate::deck::*;
 use crate::pos::*;
+use crate::solver;
 
 use std::ops::{Index, IndexMut};
 
+// 12 in the main section, plus 6 in the extra section. Strictly speaking only 21 cards guarantee a
+// set, but 18 with no set is rare enough that we just stop there so everything fits in the grid.
+pub const MAX_DEALT: usize = 18;
+
+// Cards on a puzzle board, which is just the main section
+pub const PUZZLE_CARDS: usize = 12;
+
+// How long to look for a puzzle board before settling for the closest one found
+const PUZZLE_TRIES: usize = 2000;
+
 #[derive(Copy, Clone, Debug)]
 pub struct Layout {
     // Columns 1-4 are the main section, should always be filled during normal play
@@ -78,7 +89,7 @@ impl Layout {
         // spot
     }
 
-    fn count (&self) -> u16 {
+    pub fn count (&self) -> u16 {
         u16::try_from(
             self.iter().filter(|&&c| c != None).count()
         ).unwrap()
@@ -130,7 +141,10 @@ impl Layout {
         to_return
     }
 
-    pub fn refill(&mut self, deck: &mut Deck) -> Vec<(Card, DealtPos)> {
+    // Fills gaps in the main section from the deck.
+    // If ensure_set is true, the last card dealt is chosen (by reordering the deck) so that the
+    // board ends up with at least one set, whenever the remaining deck makes that possible.
+    pub fn refill(&mut self, deck: &mut Deck, ensure_set: bool) -> Vec<(Card, DealtPos)> {
         let empties: Vec<DealtPos> = self.empties().
             into_iter()
             .filter(|&pos| pos.col() <= 3)
@@ -140,27 +154,211 @@ impl Layout {
         let mut to_return = vec![];
 
         for i in 0..to_fill {
+            if ensure_set && i + 1 == to_fill {
+                self.promote_completion(deck);
+            }
+
             let c = deck.pop().unwrap();
             to_return.push((c, DealtPos::from(empties[i])));
             self[empties[i]] = Some(c);
         }
 
+        // A main section that's already full, say from redistribute, leaves nothing to promote a
+        // card into, so a board without a set gets three more to make one
+        if ensure_set && to_fill == 0 && solver::find_set(self, deck.variant()).is_none() {
+            return self.extra3(deck, true);
+        };
+
         to_return
     }
 
-    fn extra3(&mut self, deck: &mut Deck) -> Vec<(GamePos, GamePos, Card)> {
-        let empties = self.empties();
-        debug_assert!(empties.len() <= 6 && empties.len() >= 3);
+    // If the board has no set, looks through the deck for a card that would complete one, and
+    // moves it to the top. Leaves the deck alone if the board already has a set, or if no card
+    // in the deck can help.
+    fn promote_completion(&self, deck: &mut Deck) {
+        let variant = deck.variant();
+        let cards: Vec<Card> = self.iter().filter_map(|&c| c).collect();
+        if solver::has_set(&cards, variant) { return; };
+
+        for c in solver::completions(&cards, variant) {
+            if deck.promote(c) { return; };
+        }
+    }
+
+    // Deals up to three cards from the deck into the extra section (columns 5-6), for when the
+    // user thinks there are no sets. Returns the same (card, destination) pairs as refill.
+    // Does nothing if the board is already at MAX_DEALT, or the extra section is full.
+    // ensure_set works the same as for refill.
+    pub fn extra3(&mut self, deck: &mut Deck, ensure_set: bool) -> Vec<(Card, DealtPos)> {
+        if usize::from(self.count()) + 3 > MAX_DEALT { return vec![]; };
+
+        let empties: Vec<DealtPos> = self.empties()
+            .into_iter()
+            .filter(|&pos| pos.col() >= 4)
+            .collect();
 
-        let to_fill = std::cmp::min(3, deck.len());
+        let to_fill = std::cmp::min(std::cmp::min(3, empties.len()), deck.len());
         let mut to_return = vec![];
 
         for i in 0..to_fill {
+            if ensure_set && i + 1 == to_fill {
+                self.promote_completion(deck);
+            }
+
             let c = deck.pop().unwrap();
-            to_return.push((GamePos::Deck, GamePos::from(empties[i]), c));
+            to_return.push((c, empties[i]));
             self[empties[i]] = Some(c);
         }
 
         to_return
     }
+
+    // Deals a puzzle board into the main section: twelve cards with exactly `sets` sets among
+    // them, which stay put for the whole game. Starts from the top of the deck, then swaps cards
+    // in from the rest of it, keeping any swap that doesn't move the count further from `sets`.
+    // The deck is used up, since a puzzle never deals again.
+    // Some counts are rare or impossible in some variants, so after PUZZLE_TRIES swaps this gives
+    // up and keeps the closest board it saw. Returns the number of sets on the board it dealt.
+    pub fn puzzle(&mut self, deck: &mut Deck, sets: usize) -> (Vec<(Card, DealtPos)>, usize) {
+        let variant = deck.variant();
+        let mut rng = SeededRng::new(deck.seed());
+        let mut board: Vec<Card> = (0..PUZZLE_CARDS).filter_map(|_| deck.pop()).collect();
+        let mut rest: Vec<Card> = std::iter::from_fn(|| deck.pop()).collect();
+
+        let off = |board: &[Card]| solver::count_sets(board, variant).abs_diff(sets);
+        let mut best = (off(&board), board.clone());
+
+        for _ in 0..PUZZLE_TRIES {
+            if best.0 == 0 || rest.is_empty() { break; };
+
+            let (i, j) = (rng.below(board.len()), rng.below(rest.len()));
+            let before = off(&board);
+            std::mem::swap(&mut board[i], &mut rest[j]);
+
+            let after = off(&board);
+            if after > before {
+                std::mem::swap(&mut board[i], &mut rest[j]);
+            } else if after < best.0 {
+                best = (after, board.clone());
+            }
+        }
+
+        let board = best.1;
+        let empties: Vec<DealtPos> = self.empties()
+            .into_iter()
+            .filter(|&pos| pos.col() <= 3)
+            .collect();
+
+        let mut to_return = vec![];
+        for (&c, &p) in board.iter().zip(&empties) {
+            self[p] = Some(c);
+            to_return.push((c, p));
+        }
+
+        (to_return, solver::count_sets(&board, variant))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn puzzle(seed: u64, sets: usize) -> (Layout, usize) {
+        let mut deck = Deck::with_seed(Variant::STANDARD, seed);
+        let mut lay = Layout::new([[None; 6]; 3]);
+        let (_, found) = lay.puzzle(&mut deck, sets);
+        (lay, found)
+    }
+
+    #[test]
+    fn puzzles_reach_every_count() {
+        for sets in 0..=14 {
+            for seed in 0..2 {
+                let (lay, found) = puzzle(seed, sets);
+                assert_eq!(found, sets, "seed {}", seed);
+                assert_eq!(solver::layout_count(&lay, Variant::STANDARD), sets);
+                assert_eq!(usize::from(lay.count()), PUZZLE_CARDS);
+                assert!(lay.enumerate_2d().all(|(p, c)| c.is_some() == (p.col() <= 3)));
+            }
+        }
+    }
+
+    // A full main section, straight off the top of the deck
+    fn dealt(seed: u64) -> (Layout, Deck) {
+        let mut deck = Deck::with_seed(Variant::STANDARD, seed);
+        let mut lay = Layout::new([[None; 6]; 3]);
+        lay.refill(&mut deck, false);
+        (lay, deck)
+    }
+
+    #[test]
+    fn extra_cards_stop_at_max_dealt() {
+        let (mut lay, mut deck) = dealt(3);
+        assert_eq!(usize::from(lay.count()), PUZZLE_CARDS);
+
+        for count in [15, 18] {
+            let dealt = lay.extra3(&mut deck, false);
+            assert_eq!(dealt.len(), 3);
+            assert!(dealt.iter().all(|(_, p)| p.col() >= 4));
+            assert_eq!(usize::from(lay.count()), count);
+        }
+
+        let left = deck.len();
+        assert!(lay.extra3(&mut deck, false).is_empty());
+        assert_eq!(usize::from(lay.count()), MAX_DEALT);
+        assert_eq!(deck.len(), left);
+    }
+
+    #[test]
+    fn extras_fold_back_after_a_find() {
+        let (mut lay, mut deck) = dealt(4);
+        lay.extra3(&mut deck, false);
+        let extras: Vec<Card> = lay.extras().iter().filter_map(|&p| lay[p]).collect();
+
+        let found = [DealtPos::new(0, 1), DealtPos::new(1, 3), DealtPos::new(2, 0)];
+        for p in found {
+            lay.remove(p);
+        }
+
+        let moved = lay.redistribute();
+        assert_eq!(moved.iter().map(|&(c, _, _)| c).collect::<Vec<_>>(), extras);
+        assert!(moved.iter().all(|&(c, from, to)| from.col() >= 4 && found.contains(&to) && lay[to] == Some(c)));
+        assert!(lay.enumerate_2d().all(|(p, c)| c.is_some() == (p.col() <= 3)));
+
+        // With no extras, gaps are left for refill
+        lay.remove(DealtPos::new(1, 1));
+        assert!(lay.redistribute().is_empty());
+        assert_eq!(lay.count(), 11);
+        assert_eq!(lay.refill(&mut deck, false).len(), 1);
+        assert_eq!(usize::from(lay.count()), PUZZLE_CARDS);
+    }
+
+    #[test]
+    fn refilling_keeps_a_set_on_the_board() {
+        // No set among the twelve, three of which are out in the extra section, as if they'd
+        // been dealt there and a set had since been found in the main one
+        let (mut lay, _) = puzzle(5, 0);
+        for (row, col) in [(0, 2), (1, 0), (2, 3)] {
+            lay[DealtPos::new(row, 4)] = lay[DealtPos::new(row, col)].take();
+        }
+        let board: Vec<Card> = lay.iter().filter_map(|&c| c).collect();
+        let rest = all_cards(Variant::STANDARD).into_iter().filter(|c| !board.contains(c)).collect();
+        let mut deck = Deck::from_parts(Variant::STANDARD, 5, rest);
+
+        assert_eq!(lay.redistribute().len(), 3);
+        let mut classic = lay;
+        assert!(classic.refill(&mut deck.clone(), false).is_empty());
+
+        let dealt = lay.refill(&mut deck, true);
+        assert_eq!(dealt.len(), 3);
+        assert!(dealt.iter().all(|(_, p)| p.col() >= 4));
+        assert!(solver::find_set(&lay, Variant::STANDARD).is_some());
+    }
+
+    #[test]
+    fn same_seed_same_puzzle() {
+        let (a, _) = puzzle(17, 6);
+        let (b, _) = puzzle(17, 6);
+        assert!(a.enumerate_2d().eq(b.enumerate_2d()));
+    }
 }
@@ -1,19 +1,19 @@
 use std::{io, thread, time, marker};
 use std::env;
 use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use std::sync::{Arc, Mutex, mpsc, mpsc::TryRecvError};
 use time::{Duration};
 
-use crossterm::event::{
-    self, read, poll,
-    Event, KeyEvent, KeyEventKind, MouseEvent, MouseEventKind,
-    KeyCode, KeyModifiers, MouseButton};
+use crossterm::event::{self, read, poll, KeyCode};
 
 use crossterm::{terminal, execute};
 
 use log::{info};
 use flexi_logger::{FileSpec, Logger, WriteMode};
+use clap::{Arg, ArgAction, ArgMatches};
 
 
 
@@ -54,15 +54,32 @@ pub mod pos;
 pub mod deck;
 pub mod sprites;
 pub mod layout;
+pub mod solver;
+pub mod score;
+pub mod stats;
+pub mod store;
+pub mod save;
+pub mod replay;
+pub mod daily;
+pub mod drill;
+pub mod keymap;
+pub mod bindings;
+pub mod input;
+pub mod cursor;
+pub mod net;
 pub mod term_char;
 pub mod bounds;
 pub mod id;
 
 use game::*;
+use keymap::Keymap;
+use bindings::{Action, Bindings};
+use cursor::{Cursor, Dir};
+use input::{CardClicks, Chord, Input, InputFilter};
 use animation::*;
 use util::*;
 pub use id::*;
-pub use pos::TermPos;
+pub use pos::{TermPos, DealtPos};
 
 
 // use pos::*;
@@ -73,66 +90,12 @@ pub use pos::TermPos;
 
 
 
-// fn key_to_LayoutPos(c: char) -> Option<LayoutPos> {
-//     match c {
-//         'q' | 'Q' => Some(LayoutPos::new(0, 0)),
-//         'w' | 'W' => Some(LayoutPos::new(0, 1)),
-//         'e' | 'E' => Some(LayoutPos::new(0, 2)),
-//         'r' | 'R' => Some(LayoutPos::new(0, 3)),
-//         't' | 'T' => Some(LayoutPos::new(0, 4)),
-//         'y' | 'Y' => Some(LayoutPos::new(0, 5)),
-//         'a' | 'A' => Some(LayoutPos::new(1, 0)),
-//         's' | 'S' => Some(LayoutPos::new(1, 1)),
-//         'd' | 'D' => Some(LayoutPos::new(1, 2)),
-//         'f' | 'F' => Some(LayoutPos::new(1, 3)),
-//         'g' | 'G' => Some(LayoutPos::new(1, 4)),
-//         'h' | 'H' => Some(LayoutPos::new(1, 5)),
-//         'z' | 'Z' => Some(LayoutPos::new(2, 0)),
-//         'x' | 'X' => Some(LayoutPos::new(2, 1)),
-//         'c' | 'C' => Some(LayoutPos::new(2, 2)),
-//         'v' | 'V' => Some(LayoutPos::new(2, 3)),
-//         'b' | 'B' => Some(LayoutPos::new(2, 4)),
-//         'n' | 'N' => Some(LayoutPos::new(2, 5)),
-//         _ => None
-//     }
-// }
-
-
-            // Event::Key(
-            //     KeyEvent{ code, .. }
-            // ) => match code {
-            //     KeyCode::Delete |
-            //     KeyCode::Backspace => {
-            //         break;
-            //     },
-
-            //     KeyCode::Char(c) => {
-            //         let pos_r = key_to_LayoutPos(c);
-            //         if let Some(pos) = pos_r {
-            //             let res = state.select(pos);
-            //             handle_result(res, &tx, &state)?;
-            //         };
-            //     }
-            //     _ => {}
-            // },
-                // MouseEvent{
-                //     kind:MouseEventKind::Down(MouseButton::Left),
-                //     column:x, row:y, ..
-                // }
-            // ) => {
-                // let pos_r = TermPos::new(y, x).to_LayoutPos();
-                // if let Some(pos) = pos_r {
-                //     let res = state.select(pos);
-                //     handle_result(res, &tx, &state)?;
-                // };
-            // },
-
 // Result of interpreting input
 enum InputResult {
     Quit,
     Nop,
     Msgs(Vec<Msg>),
-    Click(TermPos)
+    Pointer(Pointer)
 }
 
 // Result of interpreting collision reported by animation thread.
@@ -142,119 +105,423 @@ enum BackMsgResult {
     Msgs(Vec<Msg>)
 }
 
-fn handle_key_event(kc: KeyCode) -> InputResult {
-    match kc {
-        KeyCode::Backspace | KeyCode::Delete => InputResult::Quit,
-        _ => InputResult::Nop
+// Runs cmd against the game, and translates the outcome into messages for the animation thread.
+// Games are saved to the stats store, with their replay, as soon as they finish. Multiplayer
+// games end on the standings instead.
+fn run_command(state: &mut GameState, binds: &Bindings, cmd: Command) -> InputResult {
+    let res = state.apply(cmd);
+
+    if let Ok(Outcome::Changes(ref csets)) = res {
+        let over = csets.iter()
+            .flat_map(|cs| cs.changes.iter())
+            .find_map(|a| if let ChangeAtom::GameOver(stats) = a { Some(*stats) } else { None });
+        if let Some(stats) = over {
+            stats::record_game(&state.options(), stats);
+            replay::save_replay(state);
+
+            // A daily challenge never touches the regular save
+            if let Some(run) = daily::record_run(state) {
+                let mut ret = translate(cmd, res);
+                if let InputResult::Msgs(ref mut msgs) = ret {
+                    msgs.push(Msg::ToggleOverlay(daily::overlay(&run, binds)));
+                };
+                return ret;
+            };
+
+            // Same for multiplayer, which is never saved in the first place
+            if state.options().multiplayer() {
+                let mut ret = translate(cmd, res);
+                if let InputResult::Msgs(ref mut msgs) = ret {
+                    msgs.push(Msg::ToggleOverlay(score::standings(state.players(), binds)));
+                };
+                return ret;
+            };
+            save::discard_game();
+        };
+    };
+
+    translate(cmd, res)
+}
+
+// Rejected commands are just logged, there's nothing useful to show for them yet.
+fn translate(cmd: Command, res: std::result::Result<Outcome, CommandError>) -> InputResult {
+    match res {
+        Ok(Outcome::Changes(csets)) =>
+            InputResult::Msgs(csets.into_iter().map(Msg::ChangeMsg).collect()),
+        Ok(Outcome::Restarted(csets)) => {
+            let mut msgs = vec![Msg::Reset];
+            msgs.extend(csets.into_iter().map(Msg::ChangeMsg));
+            InputResult::Msgs(msgs)
+        },
+        Ok(Outcome::Quit) => InputResult::Quit,
+        Err(err) => {
+            info!("{:?} rejected: {:?}", cmd, err);
+            InputResult::Nop
+        }
     }
 }
 
-fn handle_back_msg(state: &mut GameState, msg: std::result::Result<BackMsg, TryRecvError>) -> BackMsgResult {
+// Where a screenshot taken now goes, named after what's being played so they sort together
+fn screenshot_path(name: &str) -> Option<PathBuf> {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let path = stats::data_dir().map(|dir| dir.join("screenshots").join(format!("{}-{}.txt", name, secs)));
+    if path.is_none() { info!("no data directory, screenshot not saved"); };
+    path
+}
+
+// Claim keys come first in multiplayer, since nothing can be picked without one, then card keys,
+// then whatever the bindings say. The cursor's keys go before all of that while it's showing,
+// and after it while it isn't.
+fn handle_key_event(state: &mut GameState, keys: Keymap, binds: &Bindings, cursor: &mut Cursor, chord: Chord) -> InputResult {
+    if let Some(dir) = binds.cursor_dir(chord).filter(|_| cursor.pos().is_some()) {
+        return step(state, cursor, dir);
+    };
+
+    let cmd = match chord.code {
+        KeyCode::Char(c @ '1'..='4') if chord.is_plain() && state.options().multiplayer() =>
+            Command::Claim(c as u8 - b'1'),
+        KeyCode::Char(c) if chord.is_plain() && keys.pos(c).is_some() => match keys.pos(c).and_then(|p| state.card_at(p)) {
+            Some(card) => Command::Select(card),
+            None => return InputResult::Nop
+        },
+
+        _ => match binds.action(chord) {
+            Some(Action::Quit) => Command::Quit,
+            Some(Action::NewGame) if state.is_over() => Command::NewGame,
+            Some(Action::NewGame | Action::Choose) => match cursor.pos().and_then(|p| state.card_at(p)) {
+                Some(card) => Command::Select(card),
+                None => return InputResult::Nop
+            },
+            Some(Action::DeselectAll) => {
+                cursor.hide();
+                Command::DeselectAll
+            },
+            Some(Action::Hint) => Command::Hint,
+            Some(Action::Undo) => Command::Undo,
+            Some(Action::Redo) => Command::Redo,
+            Some(Action::AddCards) => Command::AddCards,
+            Some(Action::Pause) => Command::Pause,
+            Some(Action::Leaderboard) => {
+                let lines = stats::default_leaderboard(&state.options().mode(), binds);
+                return InputResult::Msgs(vec![Msg::ToggleOverlay(lines)]);
+            },
+            Some(Action::Help) => return InputResult::Msgs(vec![Msg::ToggleOverlay(binds.help(keys))]),
+            Some(Action::Screenshot) => return match screenshot_path(&state.seed().to_string()) {
+                Some(path) => InputResult::Msgs(vec![Msg::Screenshot(path)]),
+                None => InputResult::Nop
+            },
+            Some(Action::Left | Action::Right | Action::Up | Action::Down) | None => match binds.cursor_dir(chord) {
+                Some(dir) => return step(state, cursor, dir),
+                None => return InputResult::Nop
+            }
+        }
+    };
+
+    run_command(state, binds, cmd)
+}
+
+// The outline catches up with the cursor at the end of the frame, see focus_msg
+fn step(state: &GameState, cursor: &mut Cursor, dir: Dir) -> InputResult {
+    cursor.step(state, dir);
+    InputResult::Nop
+}
+
+// Moves the focus outline if the cursor has moved since it was last drawn, whether the player
+// moved it or the board changed under it
+fn focus_msg(state: &GameState, cursor: &mut Cursor, shown: &mut Option<DealtPos>) -> Option<Msg> {
+    cursor.settle(state);
+    if cursor.pos() == *shown { return None; };
+    *shown = cursor.pos();
+    Some(Msg::Focus(*shown))
+}
+
+fn handle_back_msg(state: &mut GameState, binds: &Bindings, clicks: &mut CardClicks, msg: std::result::Result<BackMsg, TryRecvError>) -> BackMsgResult {
     match msg {
         Err(TryRecvError::Disconnected)
         | Ok(BackMsg::QuitMsg) => return BackMsgResult::Quit,
         Err(TryRecvError::Empty) => return BackMsgResult::Nop,
         Ok(BackMsg::Collisions(mut cards)) => 
-            if let Some(c) = cards.pop() {
-                state.select(c);
-                let csets = state.changes();
-                let msgs = csets.into_iter().map(|c| Msg::ChangeMsg(c)).collect();
-                BackMsgResult::Msgs(msgs)
+            if let Some(c) = cards.pop().and_then(|c| clicks.pick(c)) {
+                match run_command(state, binds, Command::Select(c)) {
+                    InputResult::Quit => BackMsgResult::Quit,
+                    InputResult::Msgs(msgs) => BackMsgResult::Msgs(msgs),
+                    _ => BackMsgResult::Nop
+                }
             } else {
                 BackMsgResult::Nop
             }
     }
 }
 
-fn handle_input_frame(state: &mut GameState, input: crossterm::Result<Event>) -> InputResult {
-    use InputResult::*;
-    use Event::*;
+fn handle_input_frame(
+    state: &mut GameState,
+    keys: Keymap,
+    binds: &Bindings,
+    cursor: &mut Cursor,
+    input: Option<Input>
+) -> InputResult {
+    match input {
+        Some(Input::Key(chord)) => handle_key_event(state, keys, binds, cursor, chord),
+
+        // Going back to the mouse puts the cursor away, and with it the cursor's hold on hjkl.
+        // Double-clicks are caught by card once the click's been matched to one, see CardClicks.
+        Some(Input::Click(pos) | Input::DoubleClick(pos)) => {
+            cursor.hide();
+            InputResult::Pointer(animation::Pointer::Click(pos))
+        },
+        Some(Input::RightClick(_)) => {
+            cursor.hide();
+            run_command(state, binds, Command::DeselectAll)
+        },
+
+        // Whatever the buttons are doing, so a drag still moves the highlight along
+        Some(Input::Move(pos)) => InputResult::Pointer(animation::Pointer::Move(pos)),
+        Some(Input::Lost) => InputResult::Quit,
+        None => InputResult::Nop
+    }
+}
+
+// Hands msgs to the animation thread in order
+fn send_all(snd: &mpsc::Sender<Msg>, msgs: Vec<Msg>) -> Result<()> {
+    for msg in msgs {
+        snd.send(msg)?;
+    }
+    Ok(())
+}
+
+// Plays gs until the player quits, or watches it when there's a playback. Fails if the
+// animation thread goes away, since there's no playing without it.
+fn run_game(
+    snd: &mpsc::Sender<Msg>,
+    pointer_snd: &mpsc::Sender<Pointer>,
+    rcv: &mpsc::Receiver<BackMsg>,
+    gs: &mut GameState,
+    mut playback: Option<replay::Playback>,
+    keys: Keymap,
+    binds: &Bindings
+) -> Result<()> {
+    // Replays can't be played along with, so they go unlabeled
+    if playback.is_none() {
+        snd.send(Msg::Keys(keys))?;
+    };
+    let hints = [(Action::NewGame, "play again"), (Action::Leaderboard, "leaderboard"), (Action::Quit, "quit")];
+    snd.send(Msg::OverHints(binds.hints(&hints)))?;
+
+    for chng in gs.changes() {
+        snd.send(Msg::ChangeMsg(chng))?;
+    }
+
+    let mut cursor = Cursor::default();
+    let mut focus_shown = None;
+
+    // Holding an arrow key keeps the cursor going, holding anything else does it once
+    let mut filter = InputFilter::new(binds.repeatable(keys));
+    let mut clicks = CardClicks::default();
 
-    if let Err(_) = input { return Quit; };
-    let input = input.unwrap();
+    // I think maybe screen size changes should be detected HERE, rather than in animation.
 
-    return match input {
-        Key(
-            KeyEvent {
-                code: kc,
-                modifiers: KeyModifiers::NONE,
-                kind: KeyEventKind::Press, ..
+    loop {
+        if let Some(ref mut pb) = playback {
+            for cmd in pb.due() {
+                if let InputResult::Msgs(msgs) = translate(cmd, gs.apply(cmd)) {
+                    send_all(snd, msgs)?;
+                }
             }
-        ) => handle_key_event(kc),
-
-        Mouse(
-            MouseEvent {
-                kind: MouseEventKind::Down(MouseButton::Left),
-                column,
-                row,
-                modifiers: KeyModifiers::NONE
+        } else if gs.claim_expired() {
+            if let InputResult::Msgs(msgs) = run_command(gs, binds, Command::Timeout) {
+                send_all(snd, msgs)?;
             }
-        ) => {
-            // info!("CLICK DETECTED!!");
-            Click((row, column).finto())
         }
 
-        Resize(y, x) => Quit,
-        _ => Nop
-    };
-}
+        match handle_back_msg(gs, binds, &mut clicks, rcv.try_recv()) {
+            BackMsgResult::Quit => break,
+            BackMsgResult::Nop => (),
+            BackMsgResult::Msgs(msgs) => send_all(snd, msgs)?
+        };
 
-fn main() -> Result<()> {
+        if poll(Duration::from_millis(10))? {
+            let res = if playback.is_some() {
+                handle_playback_input(binds, filter.next(read()))
+            } else {
+                handle_input_frame(gs, keys, binds, &mut cursor, filter.next(read()))
+            };
 
-    env::set_var("RUST_BACKTRACE", "1");
+            match res {
+                InputResult::Quit => break,
+                InputResult::Msgs(msgs) => send_all(snd, msgs)?,
+                InputResult::Pointer(pmsg) => pointer_snd.send(pmsg)?,
+                _ => ()
+            }
+        }
 
-    let (snd, anim_rcv) = mpsc::channel::<animation::Msg>();
-    let (click_snd, click_rcv) = mpsc::channel::<TermPos>();
-    let (anim_snd, rcv) = mpsc::channel::<animation::BackMsg>();
+        if let Some(msg) = focus_msg(gs, &mut cursor, &mut focus_shown) {
+            snd.send(msg)?;
+        };
+    };
 
-    terminal::enable_raw_mode()?;
-    execute!(io::stdout(), event::EnableMouseCapture)?;
+    Ok(())
+}
 
-    let _logger = Logger::try_with_str("info")?
-        .log_to_file(FileSpec::default().basename("log").suppress_timestamp().suffix("txt"))
-        .write_mode(WriteMode::Direct)
-        .start()?;
+// Runs the third-card drill until the player quits. Clicks go through the animation thread's
+// collision check just like in a game, the drill only ever sees which card was hit.
+fn run_drill(
+    snd: &mpsc::Sender<Msg>,
+    pointer_snd: &mpsc::Sender<Pointer>,
+    rcv: &mpsc::Receiver<BackMsg>,
+    keys: Keymap,
+    binds: &Bindings
+) -> Result<()> {
+    let mut drill = drill::Drill::new(drill::history());
+    let mut filter = InputFilter::new(vec![]);
+    for cs in drill.start() {
+        snd.send(Msg::ChangeMsg(cs))?;
+    }
+    snd.send(Msg::Status(drill.status(binds)))?;
 
-    let mut gs = GameState::default();
+    loop {
+        for cs in drill.due() {
+            snd.send(Msg::ChangeMsg(cs))?;
+        }
 
-    let handle = thread::spawn(|| {
-        animation::animate(anim_rcv, click_rcv, anim_snd)
-    });
+        match rcv.try_recv() {
+            Err(TryRecvError::Disconnected) | Ok(BackMsg::QuitMsg) => break,
+            Err(TryRecvError::Empty) => (),
+            Ok(BackMsg::Collisions(mut cards)) => {
+                if let Some((attempt, csets)) = cards.pop().and_then(|c| drill.pick(c)) {
+                    drill::record(attempt);
+                    for cs in csets {
+                        snd.send(Msg::ChangeMsg(cs))?;
+                    }
+                    snd.send(Msg::Status(drill.status(binds)))?;
+                }
+            }
+        };
 
-    for chng in gs.changes() {
-        snd.send(Msg::ChangeMsg(chng));
+        if poll(Duration::from_millis(10))? {
+            // A double-click would otherwise answer the next question too
+            match filter.next(read()) {
+                Some(Input::Key(chord)) => match binds.action(chord) {
+                    Some(Action::Quit) => break,
+                    Some(Action::Leaderboard) => { snd.send(Msg::ToggleOverlay(drill.summary(binds)))?; },
+                    Some(Action::Help) => { snd.send(Msg::ToggleOverlay(binds.help(keys)))?; },
+                    Some(Action::Screenshot) => if let Some(path) = screenshot_path("drill") {
+                        snd.send(Msg::Screenshot(path))?;
+                    },
+                    _ => ()
+                },
+                Some(Input::Click(pos)) => { pointer_snd.send(Pointer::Click(pos))?; },
+                Some(Input::Move(pos)) => { pointer_snd.send(Pointer::Move(pos))?; },
+                Some(Input::Lost) => break,
+                _ => ()
+            }
+        }
     }
 
-    // idea: to avoid excessive buffering on holding keydown, 
-    // impose time limit on pressing the same key twice.
-    //
-    // Also, I think maybe screen size changes should be detected HERE, rather than in animation.
+    Ok(())
+}
 
-    loop {
-        match handle_back_msg(&mut gs, rcv.try_recv()) {
-            BackMsgResult::Quit => break,
-            BackMsgResult::Nop => (),
-            BackMsgResult::Msgs (msgs) => {
-                for msg in msgs {
-                    snd.send(msg);
-                };
+// Plays a game hosted by a server somewhere else. Nothing is worked out locally: clicks and keys
+// are sent off as commands, and whatever changes come back are drawn as they arrive.
+fn run_client(
+    snd: &mpsc::Sender<Msg>,
+    pointer_snd: &mpsc::Sender<Pointer>,
+    rcv: &mpsc::Receiver<BackMsg>,
+    mut client: net::Client,
+    mut updates: net::Updates,
+    keys: Keymap,
+    binds: &Bindings
+) -> Result<()> {
+    info!("joined as player {} of {}", client.player() + 1, client.players());
+
+    let (up_snd, up_rcv) = mpsc::channel::<net::ServerMsg>();
+    thread::spawn(move || {
+        while let Ok(Some(msg)) = updates.recv() {
+            if up_snd.send(msg).is_err() { break; };
+        }
+    });
+
+    let hints = [(Action::NewGame, "play again"), (Action::Quit, "quit")];
+    snd.send(Msg::OverHints(binds.hints(&hints)))?;
+
+    let mut filter = InputFilter::new(vec![]);
+    let mut clicks = CardClicks::default();
+    let mut over = false;
+    'game: loop {
+        loop {
+            match up_rcv.try_recv() {
+                Ok(net::ServerMsg::Changes(cs)) => {
+                    over |= cs.changes.iter().any(|a| matches!(a, ChangeAtom::GameOver(_)));
+                    snd.send(Msg::ChangeMsg(cs))?;
+                },
+                Ok(net::ServerMsg::Reset) => {
+                    over = false;
+                    snd.send(Msg::Reset)?;
+                },
+                Ok(net::ServerMsg::Overlay(mut lines)) => {
+                    lines.extend([String::new(), binds.hints(&hints)]);
+                    snd.send(Msg::ToggleOverlay(lines))?;
+                },
+                Ok(net::ServerMsg::Rejected(reason)) => info!("server rejected command: {}", reason),
+                Ok(_) => (),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    info!("server hung up");
+                    break 'game;
+                }
+            }
+        }
+
+        match rcv.try_recv() {
+            Err(TryRecvError::Disconnected) | Ok(BackMsg::QuitMsg) => break,
+            Err(TryRecvError::Empty) => (),
+            Ok(BackMsg::Collisions(mut cards)) => if let Some(c) = cards.pop().and_then(|c| clicks.pick(c)) {
+                client.send(Command::Select(c))?;
             }
         };
 
         if poll(Duration::from_millis(10))? {
-            match handle_input_frame(&mut gs, read()) {
-                InputResult::Quit => break,
-                InputResult::Msgs(msgs) => {
-                    for msg in msgs {
-                        snd.send(msg);
-                    }
-                }
-                InputResult::Click(cmsg) => { click_snd.send(cmsg); }
-                _ => ()
-            }
+            let cmd = match filter.next(read()) {
+                // Any claim key claims for this player, the server knows who's who
+                Some(Input::Key(chord @ Chord { code: KeyCode::Char(' ' | '1'..='4'), .. }))
+                    if chord.is_plain() => Command::Claim(client.player()),
+                Some(Input::Key(chord)) => match binds.action(chord) {
+                    Some(Action::Quit) => break,
+                    Some(Action::NewGame) if over => Command::NewGame,
+                    Some(Action::DeselectAll) => Command::DeselectAll,
+                    Some(Action::AddCards) => Command::AddCards,
+                    Some(Action::Help) => {
+                        snd.send(Msg::ToggleOverlay(binds.help(keys)))?;
+                        continue;
+                    },
+                    Some(Action::Screenshot) => {
+                        if let Some(path) = screenshot_path(&format!("net-p{}", client.player() + 1)) {
+                            snd.send(Msg::Screenshot(path))?;
+                        };
+                        continue;
+                    },
+                    _ => continue
+                },
+                Some(Input::Click(pos) | Input::DoubleClick(pos)) => {
+                    pointer_snd.send(Pointer::Click(pos))?;
+                    continue;
+                },
+                Some(Input::RightClick(_)) => Command::DeselectAll,
+                Some(Input::Move(pos)) => {
+                    pointer_snd.send(Pointer::Move(pos))?;
+                    continue;
+                },
+                Some(Input::Lost) => break,
+                _ => continue
+            };
+            client.send(cmd)?;
         }
-    };
+    }
+
+    client.bye()
+}
 
+// Puts the terminal back the way it was, once the animation thread has let go of it
+fn shut_down(snd: mpsc::Sender<Msg>, handle: thread::JoinHandle<Result<()>>) -> Result<()> {
     terminal::disable_raw_mode()?;
 
     if let Err(x) = snd.send(Msg::QuitMsg){
@@ -268,6 +535,300 @@ fn main() -> Result<()> {
 
     // Can't executed on stdout until animation thread drops its lock
     execute!(io::stdout(), event::DisableMouseCapture)?;
-
     Ok(())
 }
+
+// While a replay plays, the only thing the player can do is leave
+fn handle_playback_input(binds: &Bindings, input: Option<Input>) -> InputResult {
+    match input {
+        Some(Input::Key(chord)) if binds.action(chord) == Some(Action::Quit) => InputResult::Quit,
+        Some(Input::Lost) => InputResult::Quit,
+        _ => InputResult::Nop
+    }
+}
+
+fn parse_args() -> ArgMatches {
+    let mut cmd = clap::Command::new("russet")
+        .about("Single-player SET in the terminal")
+        .arg(
+            Arg::new("classic")
+                .long("classic")
+                .action(ArgAction::SetTrue)
+                .help("Classic rules: deals aren't rearranged to guarantee a set on the board")
+        )
+        .arg(
+            Arg::new("seed")
+                .long("seed")
+                .value_name("SEED")
+                .value_parser(clap::value_parser!(u64))
+                .help("Deal the first game from this seed, e.g. to replay someone else's game")
+        )
+        .arg(
+            Arg::new("new")
+                .long("new")
+                .action(ArgAction::SetTrue)
+                .help("Start a new game, even if there's one saved from last time")
+        )
+        .arg(
+            Arg::new("daily")
+                .long("daily")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(&["seed", "classic", "attributes", "values", "super", "replay", "puzzle"])
+                .help("Play today's daily challenge: the same deal for everyone, once per day")
+        )
+        .arg(
+            Arg::new("drill")
+                .long("drill")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(&[
+                    "daily", "puzzle", "replay", "seed", "players", "serve", "connect", "classic",
+                    "attributes", "values", "super"
+                ])
+                .help("Practice: pick the card that completes a set, with stats on how you do")
+        )
+        .arg(
+            Arg::new("players")
+                .long("players")
+                .value_name("N")
+                .value_parser(clap::value_parser!(u8).range(2..=4))
+                .conflicts_with_all(&["daily", "drill", "replay"])
+                .help("Hot-seat game for N players, who press their number (1-4) to claim the board")
+        )
+        .arg(
+            Arg::new("serve")
+                .long("serve")
+                .value_name("ADDR")
+                .conflicts_with_all(&["daily", "drill", "replay", "connect"])
+                .help("Host a network game on ADDR (e.g. 0.0.0.0:7878) for --players people, default 2")
+        )
+        .arg(
+            Arg::new("connect")
+                .long("connect")
+                .value_name("ADDR")
+                .conflicts_with_all(&["daily", "drill", "replay", "puzzle", "players", "seed", "new"])
+                .help("Join the network game hosted at ADDR")
+        )
+        .arg(
+            Arg::new("puzzle")
+                .long("puzzle")
+                .value_name("N")
+                .value_parser(clap::value_parser!(u8).range(1..=14))
+                .help("Puzzle: find all N sets on a fixed board of twelve cards")
+        )
+        .arg(
+            Arg::new("keys")
+                .long("keys")
+                .value_name("LAYOUT")
+                .value_parser(Keymap::NAMES)
+                .default_value("qwerty")
+                .help("Which keys pick cards: the left half of the letter rows on this layout, or the numpad")
+        )
+        .arg(
+            Arg::new("bindings")
+                .long("bindings")
+                .value_name("FILE")
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("Read key bindings from FILE instead of bindings.txt in the data directory")
+        )
+        .arg(
+            Arg::new("replay")
+                .long("replay")
+                .value_name("FILE")
+                .value_parser(clap::value_parser!(std::path::PathBuf))
+                .help("Watch a recorded game, e.g. one of the files saved in the replays directory")
+        )
+        .arg(
+            Arg::new("speed")
+                .long("speed")
+                .value_name("X")
+                .value_parser(replay::parse_speed)
+                .default_value("1")
+                .requires("replay")
+                .help("Play the replay this many times faster than it happened")
+        )
+        .arg(
+            Arg::new("attributes")
+                .long("attributes")
+                .value_name("N")
+                .value_parser(clap::value_parser!(u8).range(1..=4))
+                .default_value("4")
+                .help("How many attributes vary between cards: color, shape, number, then fill")
+        )
+        .arg(
+            Arg::new("values")
+                .long("values")
+                .value_name("N")
+                .value_parser(clap::value_parser!(u8).range(3..=4))
+                .default_value("3")
+                .help("How many values each attribute takes, which is also the size of a set")
+        )
+        .arg(
+            Arg::new("super")
+                .long("super")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("values")
+                .help("SuperSET: find four cards that make two pairs needing the same third card")
+        );
+    let args = cmd.get_matches_mut();
+
+    // Claim keys are read before card keys, and on the numpad 1-4 are cards too. A server has no
+    // keyboard, so it's only a hot-seat game that can't have both.
+    let numpad = args.get_one::<String>("keys").and_then(|k| Keymap::from_name(k)) == Some(Keymap::Numpad);
+    if numpad && args.contains_id("players") && !args.contains_id("serve") {
+        cmd.error(
+            clap::ErrorKind::ArgumentConflict,
+            "--keys numpad can't be used with --players, whose claim keys 1-4 are on the numpad"
+        ).exit();
+    };
+
+    args
+}
+
+fn main() -> Result<()> {
+
+    // Parse before entering raw mode, so that --help and errors print normally
+    let args = parse_args();
+    let options = GameOptions {
+        guarantee_sets: !args.get_flag("classic"),
+        variant: deck::Variant {
+            attributes: *args.get_one::<u8>("attributes").unwrap(),
+            values: *args.get_one::<u8>("values").unwrap(),
+            goal: if args.get_flag("super") { deck::Goal::SuperSet } else { deck::Goal::Set }
+        },
+        puzzle: args.get_one::<u8>("puzzle").copied(),
+        players: args.get_one::<u8>("players").copied().unwrap_or(1),
+        ..Default::default()
+    };
+
+    // Load before entering raw mode too, so a bad file is reported normally
+    let replay = match args.get_one::<std::path::PathBuf>("replay") {
+        Some(path) => Some(replay::Replay::load(path)?),
+        None => None
+    };
+    let speed = *args.get_one::<f64>("speed").unwrap();
+
+    // Card keys come first, so the bindings have to know which ones are taken
+    let keys = args.get_one::<String>("keys").and_then(|k| Keymap::from_name(k)).unwrap_or_default();
+    let binds = match args.get_one::<PathBuf>("bindings").cloned().or_else(bindings::default_path) {
+        Some(path) => Bindings::load(&path, keys)?,
+        None => Bindings::defaults(keys)
+    };
+
+    // Each day's challenge can only be played once
+    let today = daily::today();
+    if args.get_flag("daily") {
+        if let Some(run) = daily::played(today)? {
+            println!("You've already played today's challenge. Come back tomorrow!");
+            println!("{}", run.result());
+            return Ok(());
+        };
+        daily::start(today)?;
+    };
+
+    env::set_var("RUST_BACKTRACE", "1");
+
+    let _logger = Logger::try_with_str("info")?
+        .log_to_file(FileSpec::default().basename("log").suppress_timestamp().suffix("txt"))
+        .write_mode(WriteMode::Direct)
+        .start()?;
+
+    // A server has no screen of its own, it just runs until its players leave
+    if let Some(addr) = args.get_one::<String>("serve") {
+        let options = GameOptions { players: options.players.max(2), ..options };
+        let server = net::Server::bind(addr.as_str(), options)?;
+        println!("Hosting on {}, waiting for {} players", server.local_addr()?, options.players);
+        return server.run();
+    };
+
+    // Connect before entering raw mode, so a refusal is reported normally
+    let client = match args.get_one::<String>("connect") {
+        Some(addr) => Some(net::Client::connect(addr.as_str())?),
+        None => None
+    };
+
+    let (snd, anim_rcv) = mpsc::channel::<animation::Msg>();
+    let (pointer_snd, pointer_rcv) = mpsc::channel::<Pointer>();
+    let (anim_snd, rcv) = mpsc::channel::<animation::BackMsg>();
+
+    terminal::enable_raw_mode()?;
+    execute!(io::stdout(), event::EnableMouseCapture)?;
+
+    let handle = thread::spawn(|| {
+        animation::animate(anim_rcv, pointer_rcv, anim_snd)
+    });
+
+    // Drills have nothing to do with regular games, so they get a loop of their own
+    if args.get_flag("drill") {
+        let res = run_drill(&snd, &pointer_snd, &rcv, keys, &binds);
+        shut_down(snd, handle)?;
+        return res;
+    };
+
+    // Losing the server is likely enough that the terminal gets put back first either way
+    if let Some((client, updates)) = client {
+        let res = run_client(&snd, &pointer_snd, &rcv, client, updates, keys, &binds);
+        shut_down(snd, handle)?;
+        return res;
+    };
+
+    // Pick up where we left off, unless asked for something specific
+    let saved = if args.get_flag("new") || args.contains_id("seed") || args.get_flag("daily")
+        || args.contains_id("puzzle") || args.contains_id("players") || replay.is_some() {
+        None
+    } else {
+        save::load_game()
+    };
+
+    let resumed = saved.and_then(|saved| match GameState::resume(options, saved) {
+        Ok(gs) => Some(gs),
+        Err(err) => {
+            info!("couldn't resume saved game: {:?}", err);
+            None
+        }
+    });
+
+    let mut gs = match (&replay, resumed, args.get_one::<u64>("seed")) {
+        (Some(rp), _, _) => GameState::with_seed(rp.options(options), rp.seed),
+        (None, _, _) if args.get_flag("daily") =>
+            GameState::with_seed(daily::options(today), daily::seed(today)),
+        (None, Some(gs), _) => gs,
+        (None, None, Some(&seed)) => GameState::with_seed(options, seed),
+        (None, None, None) => GameState::new(options)
+    };
+
+    let playback = replay.as_ref().map(|rp| replay::Playback::new(rp, speed));
+    let watching = playback.is_some();
+
+    // Losing the animation thread ends the game early, but it's saved all the same
+    let res = run_game(&snd, &pointer_snd, &rcv, &mut gs, playback, keys, &binds);
+
+    // Unfinished games are kept for next time, except daily challenges, where quitting counts
+    // as giving up, and multiplayer games, which leave the save alone. Replays are only ever
+    // watched, never saved.
+    let mut daily_run = None;
+    if !watching {
+        if let Some(day) = gs.options().daily {
+            daily_run = if gs.is_over() {
+                daily::played(day).ok().flatten()
+            } else {
+                daily::record_run(&gs)
+            };
+        } else if !gs.options().multiplayer() {
+            if gs.is_over() {
+                save::discard_game();
+            } else if let Err(err) = save::save_game(&gs) {
+                info!("failed to save game: {:?}", err);
+            }
+        }
+        replay::save_replay(&gs);
+    }
+
+    shut_down(snd, handle)?;
+
+    // Once more outside the game, where it's easy to copy
+    if let Some(run) = daily_run {
+        println!("{}", run.result());
+    };
+
+    res
+}
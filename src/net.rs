@@ -0,0 +1,433 @@
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use log::{info, warn};
+
+use crate::game::{ChangeAtom, Command, CommandError, GameOptions, GameState, Outcome};
+use crate::replay;
+use crate::score;
+use crate::util::*;
+
+pub mod wire;
+
+pub use wire::{ClientMsg, ServerMsg};
+
+// Networked multiplayer. The server owns the only GameState and is the only one to apply
+// commands to it, so there's never any question about what happened: clients just send what
+// their player did, and draw whatever ChangeSets come back, exactly as the animation thread
+// would for a local game.
+//
+// Every player's commands go through one channel, so the server sees them in a single order.
+// Selecting a card claims the board for that player, the same as a claim key in a hot-seat game,
+// and anyone else's selections are rejected until that claim is over. Whoever got there first
+// wins, and nobody can grab a card out of someone else's half-picked set.
+
+// How long the server waits for a command before checking on claims and new connections
+const POLL: Duration = Duration::from_millis(10);
+
+// How long a new connection gets to say hello. Seats are handed out one at a time, so without
+// this, one connection that never says anything would keep everyone else waiting forever.
+const HELLO_TIMEOUT: Duration = Duration::from_secs(2);
+
+fn bad(msg: &str) -> SetError {
+    SetError::new(SetErrorKind::Protocol, msg)
+}
+
+fn send_line(stream: &mut TcpStream, line: &str) -> Result<()> {
+    stream.write_all(format!("{}\n", line).as_bytes())?;
+    Ok(())
+}
+
+// Next line from the other end, or None once it's hung up
+fn read_line(reader: &mut BufReader<TcpStream>) -> Result<Option<String>> {
+    let mut line = String::new();
+    match reader.read_line(&mut line)? {
+        0 => Ok(None),
+        _ => Ok(Some(line.trim_end_matches(&['\r', '\n'][..]).to_string()))
+    }
+}
+
+pub struct Server {
+    listener: TcpListener,
+    options: GameOptions
+}
+
+impl Server {
+    // options.players is how many seats there are. The game starts once they're all taken.
+    pub fn bind(addr: impl ToSocketAddrs, options: GameOptions) -> Result<Self> {
+        Ok(Self { listener: TcpListener::bind(addr)?, options })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    // Seats every player, then runs games until they've all left
+    pub fn run(self) -> Result<()> {
+        let (snd, rcv) = mpsc::channel::<(u8, Option<ClientMsg>)>();
+        let mut seats: Vec<Option<TcpStream>> = vec![];
+
+        while seats.len() < usize::from(self.options.players) {
+            let (stream, addr) = match self.listener.accept() {
+                Ok(conn) => conn,
+                Err(err) => {
+                    warn!("accept failed: {:?}", err);
+                    continue;
+                }
+            };
+            let player = u8::ffrom(seats.len());
+            match welcome(stream, player, self.options.players) {
+                Ok((stream, reader)) => {
+                    info!("player {} joined from {}", player, addr);
+                    let snd = snd.clone();
+                    thread::spawn(move || listen(reader, player, snd));
+                    seats.push(Some(stream));
+                },
+                Err(err) => info!("turned away {}: {:?}", addr, err)
+            }
+        }
+
+        // No more seats, so anyone else is turned away between commands
+        self.listener.set_nonblocking(true)?;
+
+        let mut room = Room { state: GameState::new(self.options), seats };
+        let changes = room.state.changes();
+        room.broadcast(changes.into_iter().map(ServerMsg::Changes));
+
+        while room.seats.iter().any(Option::is_some) {
+            match rcv.recv_timeout(POLL) {
+                Ok((player, Some(ClientMsg::Command(cmd)))) => room.command(player, cmd),
+                Ok((player, Some(ClientMsg::Bye) | None)) => {
+                    info!("player {} left", player);
+                    room.seats[usize::from(player)] = None;
+                },
+                Ok((_, Some(ClientMsg::Hello(_)))) => (),
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => break
+            };
+
+            if room.state.claim_expired() {
+                if let Err(err) = room.apply(Command::Timeout) {
+                    warn!("claim timeout failed: {:?}", err);
+                };
+            };
+
+            match self.listener.accept() {
+                Ok((mut stream, addr)) => {
+                    info!("turned away {}, game in progress", addr);
+                    let _ = stream.set_nonblocking(false)
+                        .map_err(SetError::from)
+                        .and_then(|_| send_line(&mut stream, &ServerMsg::Refused(String::from("game in progress")).to_line()));
+                },
+                Err(err) if err.kind() == ErrorKind::WouldBlock => (),
+                Err(err) => warn!("accept failed: {:?}", err)
+            }
+        }
+
+        info!("everyone's left, server done");
+        Ok(())
+    }
+}
+
+// Checks a new connection speaks our protocol, and gives it a seat
+fn welcome(stream: TcpStream, player: u8, players: u8) -> Result<(TcpStream, BufReader<TcpStream>)> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut stream = stream;
+
+    stream.set_read_timeout(Some(HELLO_TIMEOUT))?;
+    let hello = read_line(&mut reader)?.ok_or_else(|| bad("hung up before saying hello"))?;
+    stream.set_read_timeout(None)?;
+    match ClientMsg::parse(&hello)? {
+        ClientMsg::Hello(wire::VERSION) => (),
+        ClientMsg::Hello(v) => {
+            let reason = format!("server speaks version {}, not {}", wire::VERSION, v);
+            send_line(&mut stream, &ServerMsg::Refused(reason.clone()).to_line())?;
+            return Err(bad(&reason));
+        },
+        _ => return Err(bad("didn't say hello"))
+    };
+
+    send_line(&mut stream, &ServerMsg::Welcome { player, players }.to_line())?;
+    Ok((stream, reader))
+}
+
+// Passes on everything a player sends, until they hang up. Lines that can't be read are dropped.
+fn listen(mut reader: BufReader<TcpStream>, player: u8, snd: mpsc::Sender<(u8, Option<ClientMsg>)>) {
+    while let Ok(Some(line)) = read_line(&mut reader) {
+        let msg = match ClientMsg::parse(&line) {
+            Ok(msg) => msg,
+            Err(err) => {
+                warn!("unreadable message from player {}: {:?} ({:?})", player, line, err.msg);
+                continue;
+            }
+        };
+
+        let bye = msg == ClientMsg::Bye;
+        if snd.send((player, Some(msg))).is_err() || bye { return; };
+    }
+
+    // Nobody to tell if the server's stopped listening already
+    let _ = snd.send((player, None));
+}
+
+// The game and everyone playing it, server side
+struct Room {
+    state: GameState,
+    seats: Vec<Option<TcpStream>>
+}
+
+impl Room {
+    // Works out what player's command means for the shared game, applies it, and tells
+    // everyone what changed. Rejections only go back to the player.
+    fn command(&mut self, player: u8, cmd: Command) {
+        let cmds = match (cmd, self.state.claimed_by()) {
+            // Selecting on an open board claims it first, as long as the card's still there. So
+            // does asking for cards, which is charged to whoever asked.
+            (Command::Select(card), None) if self.state.enumerate_cards().any(|(_, c)| c == Some(card)) =>
+                vec![Command::Claim(player), cmd],
+            (Command::AddCards, None) => match self.state.can_add_cards() {
+                Ok(()) => vec![Command::Claim(player), cmd],
+                Err(err) => return self.reject(player, err)
+            },
+            (Command::Select(_) | Command::DeselectAll | Command::AddCards, Some(p)) if p == player => vec![cmd],
+            (Command::Select(card), None) => return self.reject(player, CommandError::NotDealt(card)),
+            (Command::Select(_) | Command::DeselectAll | Command::AddCards, Some(_)) =>
+                return self.reject(player, CommandError::Claimed),
+            (Command::DeselectAll, None) => return self.reject(player, CommandError::NotClaimed),
+
+            // Nobody claims for anybody else
+            (Command::Claim(_), _) => vec![Command::Claim(player)],
+
+            // Nobody gets to throw away a game the others are still playing
+            (Command::NewGame, _) if self.state.is_over() => vec![cmd],
+            (Command::NewGame, _) => return self.reject(player, CommandError::NotOver),
+
+            // The clock is the server's business, and hints, undo and pausing are off in multiplayer
+            (Command::Timeout | Command::Pause | Command::Hint | Command::Undo | Command::Redo | Command::Quit, _) =>
+                return self.reject(player, CommandError::NotInMultiplayer)
+        };
+
+        for cmd in cmds {
+            if let Err(err) = self.apply(cmd) {
+                return self.reject(player, err);
+            }
+        }
+    }
+
+    fn apply(&mut self, cmd: Command) -> std::result::Result<(), CommandError> {
+        match self.state.apply(cmd)? {
+            Outcome::Changes(csets) => {
+                let over = csets.iter()
+                    .flat_map(|cs| cs.changes.iter())
+                    .any(|a| matches!(a, ChangeAtom::GameOver(_)));
+                self.broadcast(csets.into_iter().map(ServerMsg::Changes));
+
+                if over {
+                    replay::save_replay(&self.state);
+                    let lines = score::results(self.state.players());
+                    self.broadcast(std::iter::once(ServerMsg::Overlay(lines)));
+                };
+            },
+            Outcome::Restarted(csets) => {
+                let msgs = std::iter::once(ServerMsg::Reset).chain(csets.into_iter().map(ServerMsg::Changes));
+                self.broadcast(msgs);
+            },
+            Outcome::Quit => ()
+        };
+        Ok(())
+    }
+
+    fn reject(&mut self, player: u8, err: CommandError) {
+        info!("player {}'s command rejected: {:?}", player, err);
+        let msg = ServerMsg::Rejected(format!("{:?}", err));
+        if let Some(stream) = self.seats[usize::from(player)].as_mut() {
+            let _ = send_line(stream, &msg.to_line());
+        };
+    }
+
+    // Anyone who can't be written to any more is taken to have left
+    fn broadcast(&mut self, msgs: impl Iterator<Item=ServerMsg>) {
+        let lines: Vec<String> = msgs.map(|m| m.to_line()).collect();
+        for seat in self.seats.iter_mut() {
+            let ok = match seat {
+                Some(stream) => lines.iter().all(|l| send_line(stream, l).is_ok()),
+                None => true
+            };
+            if !ok { *seat = None; };
+        }
+    }
+}
+
+// The client end of a connection, for sending commands. What comes back is read from the
+// Updates that came with it.
+pub struct Client {
+    stream: TcpStream,
+    player: u8,
+    players: u8
+}
+
+pub struct Updates {
+    reader: BufReader<TcpStream>
+}
+
+impl Client {
+    // Connects and waits for a seat
+    pub fn connect(addr: impl ToSocketAddrs) -> Result<(Self, Updates)> {
+        let mut stream = TcpStream::connect(addr)?;
+        let mut updates = Updates { reader: BufReader::new(stream.try_clone()?) };
+        send_line(&mut stream, &ClientMsg::Hello(wire::VERSION).to_line())?;
+
+        match updates.recv()? {
+            Some(ServerMsg::Welcome { player, players }) => Ok((Self { stream, player, players }, updates)),
+            Some(ServerMsg::Refused(reason)) => Err(bad(&format!("server refused: {}", reason))),
+            Some(_) => Err(bad("server didn't say welcome")),
+            None => Err(bad("server hung up"))
+        }
+    }
+
+    pub fn send(&mut self, cmd: Command) -> Result<()> {
+        send_line(&mut self.stream, &ClientMsg::Command(cmd).to_line())
+    }
+
+    pub fn bye(mut self) -> Result<()> {
+        send_line(&mut self.stream, &ClientMsg::Bye.to_line())
+    }
+
+    // Which player this is, counting from 0
+    pub fn player(&self) -> u8 {
+        self.player
+    }
+
+    pub fn players(&self) -> u8 {
+        self.players
+    }
+}
+
+impl Updates {
+    // Next message from the server, or None once it's gone
+    pub fn recv(&mut self) -> Result<Option<ServerMsg>> {
+        match read_line(&mut self.reader)? {
+            Some(line) => ServerMsg::parse(&line).map(Some),
+            None => Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+    use std::sync::{Arc, Barrier};
+
+    fn serve(players: u8) -> (SocketAddr, thread::JoinHandle<Result<()>>) {
+        let options = GameOptions { players, ..Default::default() };
+        let server = Server::bind((Ipv4Addr::LOCALHOST, 0), options).unwrap();
+        let addr = server.local_addr().unwrap();
+        (addr, thread::spawn(move || server.run()))
+    }
+
+    // Everything the server sends until it hangs up
+    fn drain(mut updates: Updates) -> Vec<ServerMsg> {
+        let mut ret = vec![];
+        while let Ok(Some(msg)) = updates.recv() {
+            ret.push(msg);
+        }
+        ret
+    }
+
+    fn claims(msgs: &[ServerMsg]) -> Vec<u8> {
+        msgs.iter()
+            .filter_map(|m| if let ServerMsg::Changes(cs) = m { Some(cs) } else { None })
+            .flat_map(|cs| cs.changes.iter())
+            .filter_map(|a| if let ChangeAtom::Claim(p, _) = a { Some(*p) } else { None })
+            .collect()
+    }
+
+    #[test]
+    fn only_one_player_gets_a_contested_card() {
+        let (addr, server) = serve(3);
+        let mut seated: Vec<(Client, Updates)> = (0..3).map(|_| Client::connect(addr).unwrap()).collect();
+        let players: Vec<u8> = seated.iter().map(|(c, _)| c.player()).collect();
+        assert_eq!(players, [0, 1, 2]);
+
+        // Whatever's been dealt first
+        let card = loop {
+            let deal = match seated[0].1.recv().unwrap() {
+                Some(ServerMsg::Changes(cs)) => cs.changes.iter().find_map(|a| match a {
+                    ChangeAtom::Deal(c, _) => Some(*c),
+                    _ => None
+                }),
+                Some(_) => None,
+                None => panic!("server hung up")
+            };
+            if let Some(c) = deal { break c; };
+        };
+
+        let start = Arc::new(Barrier::new(seated.len()));
+        let racers: Vec<_> = seated.into_iter().map(|(mut client, updates)| {
+            let start = Arc::clone(&start);
+            thread::spawn(move || {
+                let player = client.player();
+                start.wait();
+                client.send(Command::Select(card)).unwrap();
+                client.bye().unwrap();
+                (player, drain(updates))
+            })
+        }).collect();
+        let results: Vec<(u8, Vec<ServerMsg>)> = racers.into_iter().map(|t| t.join().unwrap()).collect();
+
+        let mut claimed: Vec<u8> = results.iter().flat_map(|(_, msgs)| claims(msgs)).collect();
+        claimed.dedup();
+        assert_eq!(claimed.len(), 1, "{:?}", claimed);
+
+        for (player, msgs) in &results {
+            let rejected = msgs.iter().filter(|m| matches!(m, ServerMsg::Rejected(_))).count();
+            if *player == claimed[0] {
+                assert_eq!(rejected, 0);
+                assert_eq!(claims(msgs), [*player]);
+            } else {
+                assert_eq!(rejected, 1, "player {}", player);
+            }
+        }
+
+        server.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn again_is_refused_mid_game() {
+        let (addr, server) = serve(2);
+        let (mut first, first_updates) = Client::connect(addr).unwrap();
+        let (second, second_updates) = Client::connect(addr).unwrap();
+
+        first.send(Command::NewGame).unwrap();
+        first.bye().unwrap();
+        let msgs = drain(first_updates);
+        second.bye().unwrap();
+        let others = drain(second_updates);
+
+        assert!(msgs.contains(&ServerMsg::Rejected(format!("{:?}", CommandError::NotOver))), "{:?}", msgs);
+        assert!(!msgs.iter().chain(&others).any(|m| *m == ServerMsg::Reset));
+        server.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn a_silent_connection_doesnt_hold_up_seating() {
+        let (addr, server) = serve(2);
+        let silent = TcpStream::connect(addr).unwrap();
+
+        let joined: Vec<_> = (0..2).map(|_| thread::spawn(move || Client::connect(addr).unwrap())).collect();
+        let mut seated: Vec<(Client, Updates)> = joined.into_iter().map(|t| t.join().unwrap()).collect();
+        let mut players: Vec<u8> = seated.iter().map(|(c, _)| c.player()).collect();
+        players.sort();
+        assert_eq!(players, [0, 1]);
+
+        for (client, _) in seated.drain(..) {
+            client.bye().unwrap();
+        }
+        server.join().unwrap().unwrap();
+        drop(silent);
+    }
+}
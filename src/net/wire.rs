@@ -0,0 +1,377 @@
+use std::collections::HashSet;
+use std::str::{FromStr, SplitWhitespace};
+use std::time::Duration;
+
+use crate::deck::Card;
+use crate::game::{ChangeAtom, ChangeSet, Command, GameStats};
+use crate::pos::{DealtPos, GamePos};
+use crate::replay::{command_code, parse_command};
+use crate::save::{parse_variant, variant_str};
+use crate::score::{PlayerScore, Score};
+use crate::util::*;
+
+// The network protocol. Every message is one line of tab-separated fields, the first of which
+// says what it is. A connection starts with the client sending `russet-net <version>`, and the
+// server either welcomes it or refuses it and hangs up, so both ends always agree on VERSION.
+//
+// From the client:
+//
+//     cmd     <command>           same codes as replays, e.g. s0120
+//     again                       new game, once the last one's over
+//     bye
+//
+// From the server:
+//
+//     welcome   <player> <players>
+//     refused   <reason>
+//     changes   <stamp> <score> <atom> ...
+//     reset                       everything on screen is stale, a new game follows
+//     overlay   <line> ...
+//     rejected  <reason>          the last command didn't go through
+//
+// Atoms are space-separated words, e.g. `deal 0120 02` for a Deal of card 0120 to row 0,
+// column 2.
+
+pub const MAGIC: &str = "russet-net";
+pub const VERSION: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ClientMsg {
+    Hello(u32),
+    Command(Command),
+    Bye
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ServerMsg {
+    Welcome { player: u8, players: u8 },
+    Refused(String),
+    Changes(ChangeSet),
+    Reset,
+    Overlay(Vec<String>),
+    Rejected(String)
+}
+
+fn bad(msg: &str) -> SetError {
+    SetError::new(SetErrorKind::Protocol, msg)
+}
+
+impl ClientMsg {
+    pub fn to_line(&self) -> String {
+        match self {
+            Self::Hello(version) => format!("{} {}", MAGIC, version),
+            Self::Command(Command::NewGame) => String::from("again"),
+            Self::Command(cmd) => format!("cmd\t{}", command_code(*cmd).unwrap_or_default()),
+            Self::Bye => String::from("bye")
+        }
+    }
+
+    pub fn parse(line: &str) -> Result<Self> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        match fields[..] {
+            ["again"] => Ok(Self::Command(Command::NewGame)),
+            ["cmd", code] => parse_command(code).map(Self::Command).ok_or_else(|| bad("bad command")),
+            ["bye"] => Ok(Self::Bye),
+            [hello] => match hello.split_once(' ') {
+                Some((MAGIC, v)) => v.parse().map(Self::Hello).map_err(|_| bad("bad version")),
+                _ => Err(bad("unknown message"))
+            },
+            _ => Err(bad("unknown message"))
+        }
+    }
+}
+
+impl ServerMsg {
+    pub fn to_line(&self) -> String {
+        match self {
+            Self::Welcome { player, players } => format!("welcome\t{}\t{}", player, players),
+            Self::Refused(reason) => format!("refused\t{}", reason),
+            Self::Changes(cs) => {
+                let mut fields = vec![String::from("changes"), cs.stamp.to_string(), score_str(cs.score)];
+                fields.extend(cs.changes.iter().map(|&a| atom_str(a)));
+                fields.join("\t")
+            },
+            Self::Reset => String::from("reset"),
+            Self::Overlay(lines) => {
+                let mut fields = vec![String::from("overlay")];
+                fields.extend(lines.iter().cloned());
+                fields.join("\t")
+            },
+            Self::Rejected(reason) => format!("rejected\t{}", reason)
+        }
+    }
+
+    pub fn parse(line: &str) -> Result<Self> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        match fields[..] {
+            ["welcome", player, players] => Ok(Self::Welcome {
+                player: player.parse().map_err(|_| bad("bad player"))?,
+                players: players.parse().map_err(|_| bad("bad player"))?
+            }),
+            ["refused", reason] => Ok(Self::Refused(reason.to_string())),
+            ["changes", stamp, score, ref atoms @ ..] => {
+                let stamp = stamp.parse().map_err(|_| bad("bad stamp"))?;
+                let score = Words::new(score).score()?;
+                let changes = atoms.iter().map(|a| parse_atom(a)).collect::<Result<HashSet<_>>>()?;
+                Ok(Self::Changes(ChangeSet::new(changes, stamp, score)))
+            },
+            ["reset"] => Ok(Self::Reset),
+            ["overlay", ref lines @ ..] => Ok(Self::Overlay(lines.iter().map(|l| l.to_string()).collect())),
+            ["rejected", reason] => Ok(Self::Rejected(reason.to_string())),
+            _ => Err(bad("unknown message"))
+        }
+    }
+}
+
+fn pos_str(p: DealtPos) -> String {
+    format!("{}{}", p.row(), p.col())
+}
+
+fn game_pos_str(p: GamePos) -> String {
+    match p {
+        GamePos::Deck => String::from("deck"),
+        GamePos::LastFound { slot, of } => format!("found:{}/{}", slot, of),
+        GamePos::Link => String::from("link"),
+        GamePos::Dealt(p) => pos_str(p)
+    }
+}
+
+fn score_str(s: Score) -> String {
+    format!("{} {} {} {} {}", s.sets, s.speed, s.bad_sets, s.hints, s.bad_claims)
+}
+
+fn stats_str(s: GameStats) -> String {
+    format!("{} {} {} {} {} {} {} {} {}",
+        s.seed, s.sets_found, s.cards_left, s.score, s.bad_sets,
+        s.elapsed.as_millis(), s.hints, s.penalty.as_millis(), s.sets_to_find)
+}
+
+pub fn atom_str(atom: ChangeAtom) -> String {
+    use ChangeAtom::*;
+
+    match atom {
+        Reflow(c, p0, p1) => format!("reflow {} {} {}", c.code(), pos_str(p0), pos_str(p1)),
+        GoodMove(c, p, dst) => format!("good {} {} {}", c.code(), pos_str(p), game_pos_str(dst)),
+        BadOutline(c, p) => format!("bad {} {}", c.code(), pos_str(p)),
+        Select(c, p) => format!("select {} {}", c.code(), pos_str(p)),
+        Deselect(c, p) => format!("deselect {} {}", c.code(), pos_str(p)),
+        Fade(c, p) => format!("fade {} {}", c.code(), pos_str(p)),
+        Deal(c, p) => format!("deal {} {}", c.code(), pos_str(p)),
+        Undeal(c, p) => format!("undeal {} {}", c.code(), pos_str(p)),
+        Unfind(c, p) => format!("unfind {} {}", c.code(), pos_str(p)),
+        Hint(c, p) => format!("hint {} {}", c.code(), pos_str(p)),
+        Unhint(c, p) => format!("unhint {} {}", c.code(), pos_str(p)),
+        Link(c) => format!("link {}", c.code()),
+        Unlink(c) => format!("unlink {}", c.code()),
+        NewGame(seed, variant) => format!("new {} {}", seed, variant_str(variant)),
+        GameOver(stats) => format!("over {}", stats_str(stats)),
+        Stats(stats) => format!("stats {}", stats_str(stats)),
+        Found(c, dst) => format!("found {} {}", c.code(), game_pos_str(dst)),
+        Listed(c, set, slot) => format!("listed {} {} {}", c.code(), set, slot),
+        Unlisted(c, set, slot) => format!("unlisted {} {} {}", c.code(), set, slot),
+        AlreadyFound(c, p, set) => format!("already {} {} {}", c.code(), pos_str(p), set),
+        Claim(player, time) => format!("claim {} {}", player, time.as_millis()),
+        Unclaim(player) => format!("unclaim {}", player),
+        Player(player, p) => format!("player {} {} {} {}", player, p.sets_found, p.bad_sets, score_str(p.score)),
+        Pause => String::from("pause"),
+        Unpause => String::from("unpause")
+    }
+}
+
+pub fn parse_atom(s: &str) -> Result<ChangeAtom> {
+    use ChangeAtom::*;
+
+    let mut w = Words::new(s);
+    let atom = match w.word()? {
+        "reflow" => Reflow(w.card()?, w.pos()?, w.pos()?),
+        "good" => GoodMove(w.card()?, w.pos()?, w.game_pos()?),
+        "bad" => BadOutline(w.card()?, w.pos()?),
+        "select" => Select(w.card()?, w.pos()?),
+        "deselect" => Deselect(w.card()?, w.pos()?),
+        "fade" => Fade(w.card()?, w.pos()?),
+        "deal" => Deal(w.card()?, w.pos()?),
+        "undeal" => Undeal(w.card()?, w.pos()?),
+        "unfind" => Unfind(w.card()?, w.pos()?),
+        "hint" => Hint(w.card()?, w.pos()?),
+        "unhint" => Unhint(w.card()?, w.pos()?),
+        "link" => Link(w.card()?),
+        "unlink" => Unlink(w.card()?),
+        "new" => {
+            let seed = w.num()?;
+            let vals: Vec<&str> = w.0.by_ref().collect();
+            NewGame(seed, parse_variant(&vals)?)
+        },
+        "over" => GameOver(w.stats()?),
+        "stats" => Stats(w.stats()?),
+        "found" => Found(w.card()?, w.game_pos()?),
+        "listed" => Listed(w.card()?, w.num()?, w.num()?),
+        "unlisted" => Unlisted(w.card()?, w.num()?, w.num()?),
+        "already" => AlreadyFound(w.card()?, w.pos()?, w.num()?),
+        "claim" => Claim(w.num()?, w.millis()?),
+        "unclaim" => Unclaim(w.num()?),
+        "player" => Player(w.num()?, PlayerScore {
+            sets_found: w.num()?,
+            bad_sets: w.num()?,
+            score: w.score()?
+        }),
+        "pause" => Pause,
+        "unpause" => Unpause,
+        _ => return Err(bad("unknown change"))
+    };
+
+    match w.0.next() {
+        None => Ok(atom),
+        Some(_) => Err(bad("trailing words in change"))
+    }
+}
+
+// Reads the words of an atom one at a time, each as whatever's expected next
+struct Words<'a>(SplitWhitespace<'a>);
+
+impl<'a> Words<'a> {
+    fn new(s: &'a str) -> Self {
+        Self(s.split_whitespace())
+    }
+
+    fn word(&mut self) -> Result<&'a str> {
+        self.0.next().ok_or_else(|| bad("change cut short"))
+    }
+
+    fn num<T: FromStr>(&mut self) -> Result<T> {
+        self.word()?.parse().map_err(|_| bad("bad number"))
+    }
+
+    fn millis(&mut self) -> Result<Duration> {
+        self.num().map(Duration::from_millis)
+    }
+
+    fn card(&mut self) -> Result<Card> {
+        Card::from_code(self.word()?).ok_or_else(|| bad("bad card"))
+    }
+
+    fn pos(&mut self) -> Result<DealtPos> {
+        parse_pos(self.word()?)
+    }
+
+    fn game_pos(&mut self) -> Result<GamePos> {
+        let word = self.word()?;
+        match word {
+            "deck" => Ok(GamePos::Deck),
+            "link" => Ok(GamePos::Link),
+            _ => match word.strip_prefix("found:").and_then(|s| s.split_once('/')) {
+                Some((slot, of)) => Ok(GamePos::LastFound {
+                    slot: slot.parse().map_err(|_| bad("bad position"))?,
+                    of: of.parse().map_err(|_| bad("bad position"))?
+                }),
+                None => parse_pos(word).map(GamePos::Dealt)
+            }
+        }
+    }
+
+    fn score(&mut self) -> Result<Score> {
+        Ok(Score {
+            sets: self.num()?,
+            speed: self.num()?,
+            bad_sets: self.num()?,
+            hints: self.num()?,
+            bad_claims: self.num()?
+        })
+    }
+
+    fn stats(&mut self) -> Result<GameStats> {
+        Ok(GameStats {
+            seed: self.num()?,
+            sets_found: self.num()?,
+            cards_left: self.num()?,
+            score: self.num()?,
+            bad_sets: self.num()?,
+            elapsed: self.millis()?,
+            hints: self.num()?,
+            penalty: self.millis()?,
+            sets_to_find: self.num()?
+        })
+    }
+}
+
+fn parse_pos(s: &str) -> Result<DealtPos> {
+    let digits: Vec<u8> = s.chars()
+        .map(|ch| ch.to_digit(10).and_then(|d| u8::try_from(d).ok()))
+        .collect::<Option<_>>()
+        .ok_or_else(|| bad("bad position"))?;
+
+    match digits[..] {
+        [row, col] if row <= 2 && col <= 5 => Ok(DealtPos::new(row, col)),
+        _ => Err(bad("bad position"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deck::Variant;
+
+    fn card(code: &str) -> Card {
+        Card::from_code(code).unwrap()
+    }
+
+    fn every_atom() -> Vec<ChangeAtom> {
+        use ChangeAtom::*;
+
+        let (c, p, q) = (card("0120"), DealtPos::new(2, 5), DealtPos::new(0, 3));
+        let stats = GameStats {
+            seed: u64::MAX, sets_found: 4, cards_left: 60, score: -75, bad_sets: 2,
+            elapsed: Duration::from_millis(61_234), hints: 3, penalty: Duration::from_secs(30), sets_to_find: 0
+        };
+        let score = Score { sets: 400, speed: 123, bad_sets: -50, hints: -60, bad_claims: -50 };
+        vec![
+            Reflow(c, p, q), GoodMove(c, p, GamePos::LastFound { slot: 3, of: 4 }), GoodMove(c, p, GamePos::Dealt(q)),
+            BadOutline(c, p), Select(c, p), Deselect(c, p), Fade(c, p), Deal(c, p), Undeal(c, p), Unfind(c, p),
+            Hint(c, p), Unhint(c, p), Link(c), Unlink(c), NewGame(42, Variant::SUPER), NewGame(7, Variant::QUAD),
+            GameOver(stats), Stats(stats), Found(c, GamePos::Link), Found(c, GamePos::Deck),
+            Listed(c, 13, 2), Unlisted(c, 0, 0), AlreadyFound(c, q, 5),
+            Claim(3, Duration::from_secs(5)), Unclaim(1),
+            Player(2, PlayerScore { sets_found: 4, bad_sets: 1, score }),
+            Pause, Unpause
+        ]
+    }
+
+    #[test]
+    fn atoms_round_trip() {
+        for atom in every_atom() {
+            assert_eq!(parse_atom(&atom_str(atom)).unwrap(), atom, "{}", atom_str(atom));
+        }
+        assert!(parse_atom("deal 0120 02 extra").is_err());
+        assert!(parse_atom("deal 0120 39").is_err());
+        assert!(parse_atom("teleport 0120").is_err());
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let score = Score { sets: 100, speed: 87, ..Default::default() };
+        let msgs = vec![
+            ServerMsg::Welcome { player: 1, players: 4 },
+            ServerMsg::Refused(String::from("game in progress")),
+            ServerMsg::Changes(ChangeSet::new(every_atom().into_iter().collect(), 17, score)),
+            ServerMsg::Changes(ChangeSet::new(HashSet::new(), 0, Score::default())),
+            ServerMsg::Reset,
+            ServerMsg::Overlay(vec![String::from("FINAL STANDINGS"), String::new(), String::from("Player 2 wins!")]),
+            ServerMsg::Rejected(String::from("Claimed"))
+        ];
+        for msg in msgs {
+            assert_eq!(ServerMsg::parse(&msg.to_line()).unwrap(), msg);
+        }
+
+        let msgs = [
+            ClientMsg::Hello(VERSION),
+            ClientMsg::Command(Command::Select(card("2101"))),
+            ClientMsg::Command(Command::Claim(3)),
+            ClientMsg::Command(Command::AddCards),
+            ClientMsg::Command(Command::NewGame),
+            ClientMsg::Bye
+        ];
+        for msg in msgs {
+            assert_eq!(ClientMsg::parse(&msg.to_line()).unwrap(), msg);
+        }
+        assert!(ClientMsg::parse("hello there").is_err());
+    }
+}
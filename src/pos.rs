@@ -31,9 +31,12 @@ impl DealtPos {
 pub enum GamePos {
     #[default]
     Deck,
-    LastFound0,
-    LastFound1,
-    LastFound2,
+
+    // slot'th card of the last set found, out of `of` cards in that set
+    LastFound { slot: u8, of: u8 },
+
+    // SuperSET only: the card linking the two pairs of the last set found, above that set
+    Link,
     Dealt(DealtPos)
 }
 
@@ -244,9 +247,19 @@ impl From<(&GamePos, &Scale)> for TermPos {
         let pos = match sp {
             // subtract card_spacing_vert so it aligns with lastfound1
             GamePos::Deck => TermPos::new(bottom - CARD_SPACING_VERT,  left),
-            GamePos::LastFound0 => TermPos::new(bottom, right - LAST_FOUND_OFFSET * 2),
-            GamePos::LastFound1 => TermPos::new(bottom - CARD_SPACING_VERT, right - LAST_FOUND_OFFSET),
-            GamePos::LastFound2 => TermPos::new(bottom - CARD_SPACING_VERT * 2, right),
+
+            // Fanned out up and to the right, so the last card of the set sits against the
+            // right edge no matter how many cards are in it.
+            GamePos::LastFound { slot, of } => {
+                let (slot, of): (i16, i16) = ((*slot).into(), (*of).into());
+                TermPos::new(
+                    bottom - CARD_SPACING_VERT * slot,
+                    right - LAST_FOUND_OFFSET * (of - 1 - slot)
+                )
+            },
+
+            // Clear of the top card of a four card set, plus its outline
+            GamePos::Link => TermPos::new(bottom - CARD_SPACING_VERT * 3 - s.CARD_HEIGHT - 2, right),
 
             // to allow for correction below (stupid, i know)
             GamePos::Dealt(pos) => TermPos::from((pos, s)) + (1, 0).finto()
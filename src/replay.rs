@@ -0,0 +1,309 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+
+use crate::deck::{Card, Variant};
+use crate::game::{Command, GameOptions, GameState};
+use crate::save::{parse_variant, variant_str};
+use crate::stats::data_dir;
+use crate::util::*;
+
+// A replay is a game's seed and options, plus every command that changed it and when, counting
+// from the start of the game. Written as plain text, one event per line:
+//
+//     russet-replay 3
+//     variant 4 3 set
+//     guarantee 1
+//     seed 1234
+//     1520 s0120
+//     2210 h
+//
+// Commands are a single letter, with the card code after it for selections and the player number
+// after it for claims. Puzzles also get a `puzzle <sets>` line before the events, and multiplayer
+// games a `players <n>` one.
+
+const MAGIC: &str = "russet-replay";
+const VERSION: u32 = 3;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Replay {
+    pub variant: Variant,
+    pub guarantee_sets: bool,
+    pub puzzle: Option<u8>,
+    pub players: u8,
+    pub seed: u64,
+    pub events: Vec<(Duration, Command)>
+}
+
+fn bad(msg: &str) -> SetError {
+    SetError::new(SetErrorKind::BadSave, msg)
+}
+
+// NewGame and Quit never make it into a log, since neither is part of playing a game. Neither
+// does Pause: unpausing moves the game's clock along, so a pause leaves no gap to play back.
+pub fn command_code(cmd: Command) -> Option<String> {
+    Some(match cmd {
+        Command::Select(c) => format!("s{}", c.code()),
+        Command::DeselectAll => String::from("d"),
+        Command::Hint => String::from("h"),
+        Command::AddCards => String::from("a"),
+        Command::Undo => String::from("u"),
+        Command::Redo => String::from("r"),
+        Command::Claim(player) => format!("c{}", player),
+        Command::Timeout => String::from("t"),
+        Command::NewGame | Command::Quit | Command::Pause => return None
+    })
+}
+
+pub fn parse_command(s: &str) -> Option<Command> {
+    match s.split_at(s.len().min(1)) {
+        ("s", card) => Card::from_code(card).map(Command::Select),
+        ("d", "") => Some(Command::DeselectAll),
+        ("h", "") => Some(Command::Hint),
+        ("a", "") => Some(Command::AddCards),
+        ("u", "") => Some(Command::Undo),
+        ("r", "") => Some(Command::Redo),
+        ("c", player) => player.parse().ok().map(Command::Claim),
+        ("t", "") => Some(Command::Timeout),
+        _ => None
+    }
+}
+
+// "<ms> <command>", or None for commands that aren't logged
+pub fn event_str(&(at, cmd): &(Duration, Command)) -> Option<String> {
+    command_code(cmd).map(|code| format!("{} {}", at.as_millis(), code))
+}
+
+pub fn parse_event(ms: &str, code: &str) -> Result<(Duration, Command)> {
+    let at = ms.parse().map(Duration::from_millis).map_err(|_| bad("bad event time"))?;
+    let cmd = parse_command(code).ok_or_else(|| bad("bad command"))?;
+    Ok((at, cmd))
+}
+
+impl Replay {
+    pub fn of(state: &GameState) -> Self {
+        let options = state.options();
+        Self {
+            variant: options.variant,
+            guarantee_sets: options.guarantee_sets,
+            puzzle: options.puzzle,
+            players: options.players,
+            seed: state.seed(),
+            events: state.log().to_vec()
+        }
+    }
+
+    // Options to replay with. Anything not stored in the replay is left as in options.
+    pub fn options(&self, options: GameOptions) -> GameOptions {
+        GameOptions {
+            variant: self.variant,
+            guarantee_sets: self.guarantee_sets,
+            puzzle: self.puzzle,
+            players: self.players,
+            ..options
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut lines = vec![
+            format!("{} {}", MAGIC, VERSION),
+            format!("variant {}", variant_str(self.variant)),
+            format!("guarantee {}", u8::from(self.guarantee_sets)),
+            format!("seed {}", self.seed),
+        ];
+        if let Some(n) = self.puzzle {
+            lines.push(format!("puzzle {}", n));
+        };
+        if self.players > 1 {
+            lines.push(format!("players {}", self.players));
+        };
+        lines.extend(self.events.iter().filter_map(event_str));
+        lines.join("\n") + "\n"
+    }
+
+    pub fn from_text(text: &str) -> Result<Self> {
+        let mut lines = text.lines();
+
+        let header = lines.next().ok_or_else(|| bad("empty replay"))?;
+        match header.split_once(' ') {
+            Some((MAGIC, v)) if v == VERSION.to_string() => (),
+            Some((MAGIC, v)) => return Err(bad(&format!("replay is version {}, expected {}", v, VERSION))),
+            _ => return Err(bad("not a replay file"))
+        };
+
+        let mut replay = Self {
+            variant: Variant::STANDARD,
+            guarantee_sets: true,
+            puzzle: None,
+            players: 1,
+            seed: 0,
+            events: vec![]
+        };
+
+        for line in lines {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words[..] {
+                [] => (),
+                ["variant", ref vals @ ..] => replay.variant = parse_variant(vals)?,
+                ["guarantee", g] => replay.guarantee_sets = g != "0",
+                ["seed", seed] => replay.seed = seed.parse().map_err(|_| bad("bad seed"))?,
+                ["puzzle", n] => replay.puzzle = Some(n.parse().map_err(|_| bad("bad puzzle"))?),
+                ["players", n] => replay.players = n.parse().map_err(|_| bad("bad players"))?,
+                [ms, code] => replay.events.push(parse_event(ms, code)?),
+                _ => warn!("ignoring unknown replay line: {:?}", line)
+            }
+        }
+
+        Ok(replay)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::from_text(&fs::read_to_string(path)?)
+    }
+}
+
+pub fn replay_dir() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("replays"))
+}
+
+// Writes the game's replay to the replay directory, named after its seed. Saving the same game
+// again (e.g. after resuming it) just brings the file up to date.
+pub fn save_replay(state: &GameState) {
+    let dir = match replay_dir() {
+        Some(dir) => dir,
+        None => {
+            warn!("no data directory, replay not saved");
+            return;
+        }
+    };
+
+    let path = dir.join(format!("{}.txt", state.seed()));
+    let res = fs::create_dir_all(&dir)
+        .and_then(|_| fs::write(&path, Replay::of(state).to_text()));
+
+    match res {
+        Ok(()) => info!("saved replay to {:?}", path),
+        Err(err) => warn!("failed to save replay {:?}: {:?}", path, err)
+    }
+}
+
+// For --speed. Anything that isn't a positive number would leave the replay stuck, or worse.
+pub fn parse_speed(s: &str) -> std::result::Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(speed) if speed.is_finite() && speed > 0.0 => Ok(speed),
+        Ok(_) => Err(String::from("speed has to be more than 0")),
+        Err(err) => Err(err.to_string())
+    }
+}
+
+// Hands out a replay's commands as their time comes up, with the clock sped up by speed
+pub struct Playback {
+    events: VecDeque<(Duration, Command)>,
+    started: Instant,
+    speed: f64
+}
+
+impl Playback {
+    pub fn new(replay: &Replay, speed: f64) -> Self {
+        debug_assert!(speed.is_finite() && speed > 0.0, "see parse_speed");
+        Self {
+            events: replay.events.iter().copied().collect(),
+            started: Instant::now(),
+            speed
+        }
+    }
+
+    // Every command that should have happened by now, and hasn't been handed out yet
+    pub fn due(&mut self) -> Vec<Command> {
+        // Big enough speeds run past the end of Duration, which just means everything's due
+        let now = Duration::try_from_secs_f64(self.started.elapsed().as_secs_f64() * self.speed)
+            .unwrap_or(Duration::MAX);
+        let mut ret = vec![];
+
+        while let Some(&(at, cmd)) = self.events.front() {
+            if at > now { break; };
+            ret.push(cmd);
+            self.events.pop_front();
+        }
+
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver;
+
+    fn played(options: GameOptions, seed: u64) -> GameState {
+        let mut state = GameState::with_seed(options, seed);
+        let cards: Vec<Card> = state.enumerate_cards().filter_map(|(_, c)| c).collect();
+        let set = solver::all_sets(&cards, state.variant()).remove(0);
+        if options.multiplayer() {
+            state.apply(Command::Claim(1)).unwrap();
+        } else {
+            state.apply(Command::Hint).unwrap();
+            state.apply(Command::Select(set[1])).unwrap();
+            state.apply(Command::Undo).unwrap();
+        };
+        for card in set {
+            state.apply(Command::Select(card)).unwrap();
+        }
+        state
+    }
+
+    #[test]
+    fn speed_has_to_be_positive() {
+        assert_eq!(parse_speed("2.5"), Ok(2.5));
+        assert_eq!(parse_speed("1e-3"), Ok(0.001));
+        for bad in ["0", "-1", "NaN", "inf", "-inf", "fast", ""] {
+            assert!(parse_speed(bad).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn replays_round_trip() {
+        let options = [
+            GameOptions::default(),
+            GameOptions { puzzle: Some(3), guarantee_sets: false, ..Default::default() },
+            GameOptions { players: 3, ..Default::default() },
+            GameOptions { variant: Variant::SUPER, ..Default::default() }
+        ];
+        for options in options {
+            let replay = Replay::of(&played(options, 21));
+            let replay = Replay {
+                events: replay.events.iter()
+                    .map(|&(at, cmd)| (Duration::from_millis(u64::try_from(at.as_millis()).unwrap()), cmd))
+                    .collect(),
+                ..replay
+            };
+            assert_eq!(Replay::from_text(&replay.to_text()).unwrap(), replay);
+        }
+    }
+
+    // Same seed, same commands, same game
+    #[test]
+    fn playing_back_reproduces_the_game() {
+        let state = played(GameOptions::default(), 4);
+        let replay = Replay::of(&state);
+        let mut again = GameState::with_seed(replay.options(GameOptions::default()), replay.seed);
+        for &(_, cmd) in &replay.events {
+            again.apply(cmd).unwrap();
+        }
+        assert_eq!(again.save().dealt, state.save().dealt);
+        assert_eq!(again.save().deck, state.save().deck);
+        assert_eq!(again.stats().sets_found, state.stats().sets_found);
+    }
+
+    #[test]
+    fn fast_playback_hands_everything_out() {
+        let replay = Replay::of(&played(GameOptions::default(), 4));
+        let mut playback = Playback::new(&replay, f64::MAX);
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(playback.due().len(), replay.events.len());
+        assert!(playback.due().is_empty());
+    }
+}
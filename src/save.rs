@@ -0,0 +1,300 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use log::{info, warn};
+
+use crate::deck::{Card, Goal, Variant};
+use crate::game::{GameState, SavedGame};
+use crate::pos::DealtPos;
+use crate::score::Score;
+use crate::stats::data_dir;
+use crate::replay::{event_str, parse_event};
+use crate::util::*;
+
+// A game in progress is saved on quit, as plain text with one `key values...` line per field.
+// The first line gives the format version. Anything written by a different version is refused
+// rather than guessed at, so bump VERSION whenever the meaning of a line changes.
+//
+// Cards are written as one digit per attribute, in color, shape, number, fill order. The command
+// log is kept too, as `cmd` lines in the same format as replays. Puzzles get a `puzzle` line, and
+// a `listed` line for each set found so far.
+
+const MAGIC: &str = "russet-save";
+const VERSION: u32 = 3;
+
+pub fn save_path() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("save.txt"))
+}
+
+fn cards_str(cards: &[Card]) -> String {
+    cards.iter().map(|&c| c.code()).collect::<Vec<_>>().join(" ")
+}
+
+fn bad(msg: &str) -> SetError {
+    SetError::new(SetErrorKind::BadSave, msg)
+}
+
+fn parse_card(s: &str) -> Result<Card> {
+    Card::from_code(s).ok_or_else(|| bad("bad card"))
+}
+
+fn parse_cards(vals: &[&str]) -> Result<Vec<Card>> {
+    vals.iter().map(|s| parse_card(s)).collect()
+}
+
+fn parse_num<T: std::str::FromStr>(vals: &[&str], i: usize) -> Result<T> {
+    vals.get(i).and_then(|s| s.parse().ok()).ok_or_else(|| bad("bad number"))
+}
+
+// "<attributes> <values> <goal>", shared with replays
+pub fn variant_str(variant: Variant) -> String {
+    let goal = match variant.goal {
+        Goal::Set => "set",
+        Goal::SuperSet => "super"
+    };
+
+    format!("{} {} {}", variant.attributes, variant.values, goal)
+}
+
+pub fn parse_variant(vals: &[&str]) -> Result<Variant> {
+    let goal = match vals.get(2) {
+        Some(&"set") => Goal::Set,
+        Some(&"super") => Goal::SuperSet,
+        _ => return Err(bad("bad goal"))
+    };
+
+    let variant = Variant {
+        attributes: parse_num(vals, 0)?,
+        values: parse_num(vals, 1)?,
+        goal
+    };
+
+    // Same limits as the command line
+    let Variant { attributes, values, .. } = variant;
+    if !(1..=4).contains(&attributes) || !(3..=4).contains(&values)
+        || (goal == Goal::SuperSet && values != 3) {
+        return Err(bad("unsupported variant"));
+    };
+
+    Ok(variant)
+}
+
+pub fn to_text(saved: &SavedGame) -> String {
+    let dealt: Vec<String> = saved.dealt
+        .iter()
+        .map(|&(p, c)| format!("{}{}:{}", p.row(), p.col(), c.code()))
+        .collect();
+
+    let s = &saved.score;
+    let mut lines = vec![
+        format!("{} {}", MAGIC, VERSION),
+        format!("variant {}", variant_str(saved.variant)),
+        format!("guarantee {}", u8::from(saved.guarantee_sets)),
+        format!("seed {}", saved.seed),
+        format!("elapsed {}", saved.elapsed.as_millis()),
+        format!("penalty {}", saved.penalty.as_millis()),
+        format!("sets {}", saved.sets_found),
+        format!("hints {}", saved.hints),
+        format!("bad {}", saved.bad_sets),
+        format!("score {} {} {} {} {}", s.sets, s.speed, s.bad_sets, s.hints, s.bad_claims),
+        format!("deck {}", cards_str(&saved.deck)),
+        format!("dealt {}", dealt.join(" ")),
+        format!("selects {}", cards_str(&saved.selects)),
+    ];
+
+    if let Some(ref found) = saved.last_set_found {
+        lines.push(format!("found {}", cards_str(found)));
+    };
+
+    if let Some(n) = saved.puzzle {
+        lines.push(format!("puzzle {}", n));
+    };
+    lines.extend(saved.found.iter().map(|set| format!("listed {}", cards_str(set))));
+
+    lines.extend(saved.log.iter().filter_map(event_str).map(|e| format!("cmd {}", e)));
+
+    lines.join("\n") + "\n"
+}
+
+pub fn from_text(text: &str) -> Result<SavedGame> {
+    let mut lines = text.lines();
+
+    let header = lines.next().ok_or_else(|| bad("empty save"))?;
+    match header.split_once(' ') {
+        Some((MAGIC, v)) if v == VERSION.to_string() => (),
+        Some((MAGIC, v)) => return Err(bad(&format!("save is version {}, expected {}", v, VERSION))),
+        _ => return Err(bad("not a save file"))
+    };
+
+    let mut saved = SavedGame {
+        variant: Variant::STANDARD,
+        guarantee_sets: true,
+        puzzle: None,
+        seed: 0,
+        deck: vec![],
+        dealt: vec![],
+        last_set_found: None,
+        selects: vec![],
+        found: vec![],
+        elapsed: Duration::ZERO,
+        penalty: Duration::ZERO,
+        sets_found: 0,
+        hints: 0,
+        bad_sets: 0,
+        score: Score::default(),
+        log: vec![]
+    };
+
+    for line in lines {
+        let mut words = line.split_whitespace();
+        let key = match words.next() {
+            Some(key) => key,
+            None => continue
+        };
+        let vals: Vec<&str> = words.collect();
+
+        match key {
+            "variant" => saved.variant = parse_variant(&vals)?,
+            "guarantee" => saved.guarantee_sets = parse_num::<u8>(&vals, 0)? != 0,
+            "seed" => saved.seed = parse_num(&vals, 0)?,
+            "elapsed" => saved.elapsed = Duration::from_millis(parse_num(&vals, 0)?),
+            "penalty" => saved.penalty = Duration::from_millis(parse_num(&vals, 0)?),
+            "sets" => saved.sets_found = parse_num(&vals, 0)?,
+            "hints" => saved.hints = parse_num(&vals, 0)?,
+            "bad" => saved.bad_sets = parse_num(&vals, 0)?,
+            "score" => saved.score = Score {
+                sets: parse_num(&vals, 0)?,
+                speed: parse_num(&vals, 1)?,
+                bad_sets: parse_num(&vals, 2)?,
+                hints: parse_num(&vals, 3)?,
+                bad_claims: parse_num(&vals, 4)?
+            },
+            "deck" => saved.deck = parse_cards(&vals)?,
+            "dealt" => for v in vals {
+                let (pos, card) = v.split_once(':').ok_or_else(|| bad("bad dealt card"))?;
+                let pos: Vec<u8> = pos.chars()
+                    .map(|ch| ch.to_digit(10).and_then(|d| u8::try_from(d).ok()))
+                    .collect::<Option<_>>()
+                    .ok_or_else(|| bad("bad position"))?;
+                match pos[..] {
+                    [row, col] if row <= 2 && col <= 5 =>
+                        saved.dealt.push((DealtPos::new(row, col), parse_card(card)?)),
+                    _ => return Err(bad("bad position"))
+                }
+            },
+            "selects" => saved.selects = parse_cards(&vals)?,
+            "found" => saved.last_set_found = Some(parse_cards(&vals)?),
+            "puzzle" => saved.puzzle = Some(parse_num(&vals, 0)?),
+            "listed" => saved.found.push(parse_cards(&vals)?),
+            "cmd" => match vals[..] {
+                [ms, code] => saved.log.push(parse_event(ms, code)?),
+                _ => return Err(bad("bad command"))
+            },
+            _ => warn!("ignoring unknown save line: {:?}", line)
+        }
+    }
+
+    Ok(saved)
+}
+
+// Saves the game to the user's data directory, replacing any earlier save
+pub fn save_game(state: &GameState) -> Result<()> {
+    let path = save_path().ok_or_else(|| bad("no data directory"))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    };
+
+    fs::write(&path, to_text(&state.save()))?;
+    info!("saved game to {:?}", path);
+    Ok(())
+}
+
+// The saved game, if there is one. A save that can't be read is logged and left where it is.
+pub fn load_game() -> Option<SavedGame> {
+    let path = save_path()?;
+    let text = fs::read_to_string(&path).ok()?;
+
+    match from_text(&text) {
+        Ok(saved) => Some(saved),
+        Err(err) => {
+            warn!("couldn't read saved game {:?}: {:?}", path, err);
+            None
+        }
+    }
+}
+
+// Throws the save away, once its game is finished or replaced
+pub fn discard_game() {
+    if let Some(path) = save_path() {
+        if path.exists() {
+            if let Err(err) = fs::remove_file(&path) {
+                warn!("couldn't remove saved game {:?}: {:?}", path, err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{Command, GameOptions};
+    use crate::solver;
+
+    // Times are only written to the millisecond
+    fn ms(d: Duration) -> Duration {
+        Duration::from_millis(u64::try_from(d.as_millis()).unwrap())
+    }
+
+    fn round_trip(state: &GameState) {
+        let saved = state.save();
+        let saved = SavedGame {
+            elapsed: ms(saved.elapsed),
+            penalty: ms(saved.penalty),
+            log: saved.log.iter().map(|&(at, cmd)| (ms(at), cmd)).collect(),
+            ..saved
+        };
+        assert_eq!(from_text(&to_text(&saved)).unwrap(), saved);
+    }
+
+    fn take_a_set(state: &mut GameState) {
+        let cards: Vec<Card> = state.enumerate_cards().filter_map(|(_, c)| c).collect();
+        for card in solver::all_sets(&cards, state.variant()).remove(0) {
+            state.apply(Command::Select(card)).unwrap();
+        }
+    }
+
+    #[test]
+    fn game_in_progress_round_trips() {
+        let mut state = GameState::with_seed(GameOptions::default(), 99);
+        round_trip(&state);
+
+        take_a_set(&mut state);
+        state.apply(Command::Hint).unwrap();
+        let card = state.card_at(DealtPos::new(2, 3)).unwrap();
+        state.apply(Command::Select(card)).unwrap();
+        round_trip(&state);
+    }
+
+    #[test]
+    fn puzzle_and_super_round_trip() {
+        let options = GameOptions { puzzle: Some(4), ..Default::default() };
+        let mut state = GameState::with_seed(options, 8);
+        take_a_set(&mut state);
+        assert_eq!(state.save().found.len(), 1);
+        round_trip(&state);
+
+        let options = GameOptions { variant: Variant::SUPER, ..Default::default() };
+        let mut state = GameState::with_seed(options, 8);
+        take_a_set(&mut state);
+        round_trip(&state);
+    }
+
+    #[test]
+    fn other_versions_are_refused() {
+        let text = to_text(&GameState::with_seed(GameOptions::default(), 1).save());
+        let text = text.replacen(&format!("{} {}", MAGIC, VERSION), &format!("{} {}", MAGIC, VERSION + 1), 1);
+        assert!(from_text(&text).is_err());
+        assert!(from_text("").is_err());
+    }
+}
@@ -0,0 +1,124 @@
+use std::time::Duration;
+
+use crate::bindings::{Action, Bindings};
+
+// How many points everything is worth. Penalties are given as positive numbers, and subtracted.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ScoringRules {
+    pub set: i32,
+
+    // Extra points for a set found straight away, shrinking to nothing once bonus_window has
+    // passed since the previous set (or the start of the game)
+    pub speed_bonus: i32,
+    pub bonus_window: Duration,
+
+    pub bad_set: i32,
+
+    // Per card revealed
+    pub hint: i32,
+
+    // Asking for more cards while the board still has a set on it
+    pub bad_claim: i32
+}
+
+impl Default for ScoringRules {
+    fn default() -> Self {
+        Self {
+            set: 100,
+            speed_bonus: 100,
+            bonus_window: Duration::from_secs(60),
+            bad_set: 25,
+            hint: 20,
+            bad_claim: 50
+        }
+    }
+}
+
+impl ScoringRules {
+    pub fn bonus(&self, taken: Duration) -> i32 {
+        if taken >= self.bonus_window || self.bonus_window.is_zero() { return 0; };
+        let left = (self.bonus_window - taken).as_secs_f64() / self.bonus_window.as_secs_f64();
+        (f64::from(self.speed_bonus) * left).round() as i32
+    }
+}
+
+// Breakdown of a score. Penalties are stored as the (negative) points they take off.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Score {
+    pub sets: i32,
+    pub speed: i32,
+    pub bad_sets: i32,
+    pub hints: i32,
+    pub bad_claims: i32
+}
+
+impl Score {
+    pub fn total(&self) -> i32 {
+        self.sets + self.speed + self.bad_sets + self.hints + self.bad_claims
+    }
+}
+
+// Multiplayer only: one player's share of the game
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct PlayerScore {
+    pub sets_found: u32,
+    pub bad_sets: u32,
+    pub score: Score
+}
+
+// Lines for the overlay at the end of a multiplayer game, best first
+pub fn standings(players: &[PlayerScore], binds: &Bindings) -> Vec<String> {
+    let mut lines = results(players);
+    lines.push(String::new());
+    lines.push(binds.hints(&[(Action::NewGame, "play again"), (Action::Quit, "quit")]));
+    lines
+}
+
+// Standings without the keys for what to do next. A server can't know its players' bindings, so
+// this is what it sends, and each client adds its own.
+pub fn results(players: &[PlayerScore]) -> Vec<String> {
+    let mut ranked: Vec<(usize, &PlayerScore)> = players.iter().enumerate().collect();
+    ranked.sort_by_key(|(_, p)| (-p.score.total(), -i64::from(p.sets_found)));
+
+    let mut lines = vec![String::from("FINAL STANDINGS"), String::new()];
+    lines.push(format!("{:<10}  {:>6}  {:>4}  {:>6}", "player", "score", "sets", "misses"));
+    for (i, p) in &ranked {
+        lines.push(format!("{:<10}  {:>6}  {:>4}  {:>6}",
+            format!("player {}", i + 1), p.score.total(), p.sets_found, p.bad_sets));
+    }
+
+    lines.push(String::new());
+    let best: Vec<usize> = ranked.iter()
+        .filter(|(_, p)| p.score.total() == ranked[0].1.score.total())
+        .map(|(i, _)| i + 1)
+        .collect();
+    lines.push(match best[..] {
+        [winner] => format!("Player {} wins!", winner),
+        _ => String::from("It's a tie!")
+    });
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bonus_shrinks_to_nothing_over_the_window() {
+        let rules = ScoringRules::default();
+        assert_eq!(rules.bonus(Duration::ZERO), rules.speed_bonus);
+        assert_eq!(rules.bonus(rules.bonus_window / 2), rules.speed_bonus / 2);
+        assert_eq!(rules.bonus(rules.bonus_window / 4), rules.speed_bonus * 3 / 4);
+        assert_eq!(rules.bonus(rules.bonus_window), 0);
+        assert_eq!(rules.bonus(rules.bonus_window * 2), 0);
+
+        let later: Vec<i32> = (0..=6).map(|s| rules.bonus(Duration::from_secs(s * 10))).collect();
+        assert!(later.windows(2).all(|w| w[0] > w[1]));
+    }
+
+    #[test]
+    fn no_window_means_no_bonus() {
+        let rules = ScoringRules { bonus_window: Duration::ZERO, ..Default::default() };
+        assert_eq!(rules.bonus(Duration::ZERO), 0);
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::deck::{Card, Goal, Variant, ATTRIBUTES, MAX_VALUES};
+use crate::game::is_a_set;
+use crate::layout::Layout;
+use crate::pos::DealtPos;
+
+// Everything in here works off the fact that any set_size - 1 cards have at most one card that
+// completes them into a set. So instead of checking every combination of set_size cards, we
+// check every combination of one fewer and look the last card up. For standard decks, that's
+// n^2 instead of n^3.
+//
+// SuperSETs work the same way one level down: every pair of cards has exactly one third card,
+// so we group pairs by it, and any two pairs in the same group make a SuperSET.
+
+/// Returns the unique card that forms a set with cards, if there is one. cards should be one
+/// short of a full set for the variant. Only makes sense for Goal::Set.
+pub fn complete(cards: &[Card], variant: Variant) -> Option<Card> {
+    if cards.is_empty() { return None; };
+
+    let mut attrs = [0; ATTRIBUTES];
+
+    for (a, attr) in attrs.iter_mut().enumerate() {
+        let first = cards[0].attrs()[a];
+
+        // If the values all match, the last card has to match too. Otherwise they all have to be
+        // different, and the last card takes whichever value hasn't been used yet.
+        *attr = if cards.iter().all(|c| c.attrs()[a] == first) {
+            first
+        } else {
+            let mut seen = [false; MAX_VALUES];
+            for c in cards {
+                let v = c.attrs()[a];
+                if seen[v] { return None; };
+                seen[v] = true;
+            }
+            (0..usize::from(variant.values)).find(|&v| !seen[v])?
+        };
+    }
+
+    Some(Card::from_attrs(attrs))
+}
+
+/// Returns the unique card that forms a standard (three card) set with c0 and c1.
+/// If c0 == c1 this is just the same card again, which obviously isn't a real set.
+pub fn third(c0: Card, c1: Card) -> Card {
+    complete(&[c0, c1], Variant::STANDARD)
+        .expect("any two cards with three values per attribute have a third")
+}
+
+// Every increasing list of k indices below n
+fn combinations(n: usize, k: usize) -> Vec<Vec<usize>> {
+    let mut ret = vec![];
+    let mut partial = vec![];
+
+    fn go(n: usize, k: usize, partial: &mut Vec<usize>, ret: &mut Vec<Vec<usize>>) {
+        if partial.len() == k {
+            ret.push(partial.clone());
+            return;
+        };
+
+        let from = partial.last().map_or(0, |&i| i + 1);
+        for i in from..n {
+            partial.push(i);
+            go(n, k, partial, ret);
+            partial.pop();
+        }
+    }
+
+    go(n, k, &mut partial, &mut ret);
+    ret
+}
+
+/// Returns the order that puts the two pairs of a SuperSET next to each other, along with the
+/// card both pairs complete to. None if cards aren't a SuperSET.
+pub fn super_pairs(cards: &[Card]) -> Option<([usize; 4], Card)> {
+    if cards.len() != 4 { return None; };
+    if cards.iter().enumerate().any(|(i, c)| cards[..i].contains(c)) { return None; };
+
+    [[0, 1, 2, 3], [0, 2, 1, 3], [0, 3, 1, 2]]
+        .into_iter()
+        .find(|&[a, b, c, d]| third(cards[a], cards[b]) == third(cards[c], cards[d]))
+        .map(|order| (order, third(cards[order[0]], cards[order[1]])))
+}
+
+/// Whether cards are exactly one complete set, by the variant's rules
+pub fn is_valid(cards: &[Card], variant: Variant) -> bool {
+    match variant.goal {
+        Goal::Set => cards.len() == variant.set_size() && is_a_set(cards),
+        Goal::SuperSet => super_pairs(cards).is_some()
+    }
+}
+
+/// Returns every set among cards, as increasing index lists into the slice. Each set is reported
+/// exactly once. Duplicate cards in the slice are ignored after their first appearance.
+pub fn set_indices(cards: &[Card], variant: Variant) -> Vec<Vec<usize>> {
+    match variant.goal {
+        Goal::Set => plain_set_indices(cards, variant),
+        Goal::SuperSet => super_set_indices(cards)
+    }
+}
+
+fn plain_set_indices(cards: &[Card], variant: Variant) -> Vec<Vec<usize>> {
+    let mut index = HashMap::with_capacity(cards.len());
+    for (i, &c) in cards.iter().enumerate() {
+        index.entry(c).or_insert(i);
+    }
+
+    let mut ret = vec![];
+
+    for mut set in combinations(cards.len(), variant.set_size() - 1) {
+        let chosen: Vec<Card> = set.iter().map(|&i| cards[i]).collect();
+        if chosen.iter().enumerate().any(|(i, c)| chosen[..i].contains(c)) { continue; };
+
+        // only accept a last card that comes after the others, so that every set gets found
+        // exactly once
+        if let Some(&k) = complete(&chosen, variant).and_then(|c| index.get(&c)) {
+            if k > *set.last().unwrap() {
+                set.push(k);
+                debug_assert!(is_a_set(&set.iter().map(|&i| cards[i]).collect::<Vec<_>>()));
+                ret.push(set);
+            }
+        }
+    }
+
+    ret
+}
+
+fn super_set_indices(cards: &[Card]) -> Vec<Vec<usize>> {
+    let mut by_third: HashMap<Card, Vec<(usize, usize)>> = HashMap::new();
+
+    for pair in combinations(cards.len(), 2) {
+        let (i, j) = (pair[0], pair[1]);
+        if cards[..j].contains(&cards[j]) || cards[..i].contains(&cards[i]) { continue; };
+        by_third.entry(third(cards[i], cards[j])).or_default().push((i, j));
+    }
+
+    // Two different pairs with the same third card can't share a card, since a card and a third
+    // card pin down the other one. The same four cards can pair up more than one way though,
+    // hence the dedup.
+    let mut found = BTreeSet::new();
+    for pairs in by_third.values() {
+        for (k, &(a, b)) in pairs.iter().enumerate() {
+            for &(c, d) in &pairs[k + 1..] {
+                let mut set = vec![a, b, c, d];
+                set.sort_unstable();
+                found.insert(set);
+            }
+        }
+    }
+
+    found.into_iter().collect()
+}
+
+/// Returns every card that would complete a set with some of cards, whether or not it's in
+/// cards already.
+pub fn completions(cards: &[Card], variant: Variant) -> Vec<Card> {
+    if variant.goal == Goal::SuperSet {
+        return super_completions(cards);
+    };
+
+    combinations(cards.len(), variant.set_size() - 1)
+        .into_iter()
+        .filter_map(|set| {
+            let chosen: Vec<Card> = set.iter().map(|&i| cards[i]).collect();
+            complete(&chosen, variant).filter(|c| !chosen.contains(c))
+        })
+        .collect()
+}
+
+// Any three cards can be finished into a SuperSET three ways, one for each card that could be
+// paired with the new one
+fn super_completions(cards: &[Card]) -> Vec<Card> {
+    combinations(cards.len(), 3)
+        .into_iter()
+        .flat_map(|set| {
+            let [x, y, z] = [cards[set[0]], cards[set[1]], cards[set[2]]];
+            [(x, y, z), (y, x, z), (z, x, y)]
+                .into_iter()
+                .map(|(lone, p0, p1)| third(lone, third(p0, p1)))
+                .filter(move |c| ![x, y, z].contains(c))
+        })
+        .collect()
+}
+
+/// Returns every set among cards.
+pub fn all_sets(cards: &[Card], variant: Variant) -> Vec<Vec<Card>> {
+    set_indices(cards, variant)
+        .into_iter()
+        .map(|set| set.into_iter().map(|i| cards[i]).collect())
+        .collect()
+}
+
+pub fn count_sets(cards: &[Card], variant: Variant) -> usize {
+    set_indices(cards, variant).len()
+}
+
+pub fn has_set(cards: &[Card], variant: Variant) -> bool {
+    // Could stop early here, but boards are small enough that it doesn't matter
+    !set_indices(cards, variant).is_empty()
+}
+
+/// Returns every set currently dealt on the layout, by position.
+pub fn layout_sets(lay: &Layout, variant: Variant) -> Vec<Vec<DealtPos>> {
+    let (positions, cards): (Vec<DealtPos>, Vec<Card>) = lay
+        .enumerate_2d()
+        .filter_map(|(pos, c)| c.map(|c| (pos, c)))
+        .unzip();
+
+    set_indices(&cards, variant)
+        .into_iter()
+        .map(|set| set.into_iter().map(|i| positions[i]).collect())
+        .collect()
+}
+
+pub fn layout_count(lay: &Layout, variant: Variant) -> usize {
+    layout_sets(lay, variant).len()
+}
+
+/// Returns any one set on the layout, if there is one.
+pub fn find_set(lay: &Layout, variant: Variant) -> Option<Vec<DealtPos>> {
+    layout_sets(lay, variant).into_iter().next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cards(codes: &[&str]) -> Vec<Card> {
+        codes.iter().map(|s| Card::from_code(s).unwrap()).collect()
+    }
+
+    #[test]
+    fn third_card_matches_or_differs_per_attribute() {
+        let [a, b] = [cards(&["0000"])[0], cards(&["0120"])[0]];
+        assert_eq!(third(a, b), cards(&["0210"])[0]);
+        assert_eq!(third(a, a), a);
+
+        // Every pair in a small deck finishes to a valid set
+        let deck = crate::deck::all_cards(Variant::JUNIOR);
+        for (i, &x) in deck.iter().enumerate() {
+            for &y in &deck[i + 1..] {
+                assert!(is_valid(&[x, y, third(x, y)], Variant::STANDARD));
+            }
+        }
+    }
+
+    #[test]
+    fn is_valid_checks_size_and_rules() {
+        assert!(is_valid(&cards(&["0000", "1111", "2222"]), Variant::STANDARD));
+        assert!(is_valid(&cards(&["0120", "0121", "0122"]), Variant::STANDARD));
+        assert!(!is_valid(&cards(&["0000", "1111", "2220"]), Variant::STANDARD));
+        assert!(!is_valid(&cards(&["0000", "1111"]), Variant::STANDARD));
+        assert!(is_valid(&cards(&["0000", "1111", "2222", "3333"]), Variant::QUAD));
+        assert!(!is_valid(&cards(&["0000", "1111", "2222"]), Variant::QUAD));
+    }
+
+    #[test]
+    fn super_sets_pair_up_through_a_shared_third() {
+        // 0000 + 1200 and 0100 + 1100 both need 2100
+        let four = cards(&["0000", "0100", "1100", "1200"]);
+        let (order, link) = super_pairs(&four).unwrap();
+        assert_eq!(link, cards(&["2100"])[0]);
+        assert_eq!(third(four[order[0]], four[order[1]]), third(four[order[2]], four[order[3]]));
+        assert!(is_valid(&four, Variant::SUPER));
+        assert!(!is_valid(&cards(&["0000", "0100", "1100", "2222"]), Variant::SUPER));
+        assert!(!is_valid(&four[..3], Variant::SUPER));
+    }
+
+    #[test]
+    fn layout_sets_finds_every_set_once() {
+        let board = cards(&[
+            "0000", "1111", "2222", "0120",
+            "0121", "0122", "1000", "2000",
+            "1201", "2102", "0012", "2211"
+        ]);
+        let mut grid = [[None; 6]; 3];
+        for (i, &c) in board.iter().enumerate() {
+            grid[i / 4][i % 4] = Some(c);
+        }
+        let lay = Layout::new(grid);
+
+        let sets = layout_sets(&lay, Variant::STANDARD);
+        let brute = combinations(board.len(), 3).into_iter()
+            .filter(|s| is_a_set(&s.iter().map(|&i| board[i]).collect::<Vec<_>>()))
+            .count();
+        assert_eq!(sets.len(), brute);
+        assert!(sets.len() >= 3);
+        for set in &sets {
+            let found: Vec<Card> = set.iter().map(|&p| lay[p].unwrap()).collect();
+            assert!(is_valid(&found, Variant::STANDARD));
+        }
+        assert!(sets.contains(&vec![DealtPos::new(0, 0), DealtPos::new(0, 1), DealtPos::new(0, 2)]));
+
+        let supers = layout_sets(&lay, Variant::SUPER);
+        let brute = combinations(board.len(), 4).into_iter()
+            .filter(|s| super_pairs(&s.iter().map(|&i| board[i]).collect::<Vec<_>>()).is_some())
+            .count();
+        assert_eq!(supers.len(), brute);
+        assert!(!supers.is_empty());
+    }
+}
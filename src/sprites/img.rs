@@ -1,6 +1,8 @@
 use super::grid::*;
 use super::SpriteCell;
 use crate::pos::TermPos;
+use crate::term_char::TermChar;
+use crossterm::style::Color;
 use crate::Result;
 use crate::util::FInto as _;
 
@@ -34,6 +36,22 @@ impl Img {
         Self (Grid::new(height, width, px))
     }
 
+    // A solid block of text. Lines shorter than the longest one are padded out with bg.
+    pub fn text(s: &str, fg: Color, bg: Color) -> Self {
+        let lines: Vec<&str> = s.lines().collect();
+        let width = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+        let mut img = Self::rect(lines.len(), width, SpriteCell::Opaque(TermChar::solid(bg)));
+
+        for (y, line) in lines.iter().enumerate() {
+            for (x, c) in line.chars().enumerate() {
+                img.set((y, x), SpriteCell::Opaque(TermChar::new(c, fg, bg)))
+                    .expect("text img is sized to fit every line");
+            }
+        }
+
+        img
+    }
+
     pub fn get(&self, pos: (usize, usize)) -> Result<SpriteCell> {
         self.0.get(pos)
     }
@@ -121,6 +121,13 @@ impl Sprite {
         self.set_rel(pos - self.anchor, cel)
     }
 
+    // Swaps out the whole picture, dirtying both the old and new area in case they differ
+    pub fn set_img(&mut self, img: Img) {
+        self.dirty_all();
+        self.img = img;
+        self.dirty_all();
+    }
+
     // Ordinary field accesses
     
     pub fn anchor(&self) -> TermPos {
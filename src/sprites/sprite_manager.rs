@@ -86,6 +86,31 @@ impl SpriteManager {
         self.dirt.clear();
         writer.flush();
     }
+
+    // What's on screen as plain text, one line per row, without colors or trailing spaces
+    pub fn text(&self) -> String {
+        let mut ret = String::new();
+        for y in 0..TS.height() {
+            let line: String = (0..TS.width()).map(|x| cell_at(&self.sprites, y, x).get_c()).collect();
+            ret.push_str(line.trim_end());
+            ret.push('\n');
+        }
+        ret
+    }
+}
+
+// Whatever the frontmost opaque sprite at (y, x) shows, or the background if there's none
+fn cell_at(sprites: &[Stn], y: i16, x: i16) -> TermChar {
+    for sprite in sprites {
+        let cel = sprite.borrow_mut()
+            .get((y, x).finto())
+            .unwrap_or(Transparent);
+
+        if let Opaque(tc) = cel {
+            return tc;
+        }
+    }
+    TermChar::default()
 }
 
 fn queue_term(writer: &mut impl Write, term: Termable, y: u16, x: u16) {
@@ -96,7 +121,7 @@ fn queue_term(writer: &mut impl Write, term: Termable, y: u16, x: u16) {
     );
 }
 
-fn write_line(writer: &mut impl Write, sprites: &Vec<Stn>, y: i16, line: &Vec<i16>) {
+fn write_line(writer: &mut impl Write, sprites: &[Stn], y: i16, line: &Vec<i16>) {
 
     let mut term = Termable::default();
     let mut start: i16 = 0;
@@ -110,18 +135,7 @@ fn write_line(writer: &mut impl Write, sprites: &Vec<Stn>, y: i16, line: &Vec<i1
         at_least_one = true;
 
         // We write the default background color if we don't hit anything opaque
-        let mut char_to_write = TermChar::default();
-
-        for sprite in sprites {
-            let cel = sprite.borrow_mut()
-                .get((y, x).finto())
-                .unwrap_or(Transparent);
-
-            if let Opaque(tc) = cel {
-                char_to_write = tc;
-                break;
-            }
-        }
+        let char_to_write = cell_at(sprites, y, x);
 
         // If there was a jump, or if pushing onto the current termable fails,
         // then write the old termable and start a new one. In either case, termable is
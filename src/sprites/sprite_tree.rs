@@ -193,6 +193,20 @@ impl SpriteTree {
         }
     }
 
+    // Detaches the tree with the given id, from anywhere below self, and hands it back.
+    pub fn remove_tree(&mut self, id: Id<Self>) -> Option<Self> {
+        if let Some(i) = self.children.iter().position(|c| c.id == id) {
+            return Some(self.children.remove(i));
+        };
+
+        for child in &mut self.children {
+            if let Some(tr) = child.remove_tree(id) {
+                return Some(tr);
+            }
+        }
+        None
+    }
+
     pub fn push_sprite(&mut self, sp: Stn, ins: Inheritances) -> Id<Self> {
         self.insert_sprite(sp, None, ins).unwrap()
     }
@@ -235,6 +249,15 @@ impl SpriteTree {
         sp.dirty_all();
     }
 
+    // Marks everything this tree currently covers as dirty. Call before AND after moving a tree,
+    // so that both the vacated area and the new one get redrawn.
+    pub fn dirty_all(&self) {
+        self.node.borrow().dirty_all();
+        for child in &self.children {
+            child.dirty_all();
+        }
+    }
+
     // Hierarchical sprite manipulation
 
     pub fn shift(&mut self, delta: TermPos, ins: InheritanceType) {
@@ -0,0 +1,252 @@
+use std::env;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::bindings::{Action, Bindings};
+use crate::game::{GameOptions, GameStats};
+use crate::store::{self, Line, Store};
+use crate::util::*;
+
+// Every finished game is appended to a tab-separated file in the user's data directory, one line
+// per game. Tabs rather than anything fancier so the file can be pasted straight into a
+// spreadsheet.
+
+const HEADER: &str = "finished\tmode\tseed\ttime_ms\tpenalty_ms\tsets\tbad_sets\thints\tscore";
+const LEADERBOARD_LEN: usize = 10;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Record {
+    // Seconds since the unix epoch
+    pub finished: u64,
+    pub mode: String,
+    pub seed: u64,
+    pub elapsed: Duration,
+    pub penalty: Duration,
+    pub sets_found: u32,
+    pub bad_sets: u32,
+    pub hints: u32,
+    pub score: i32
+}
+
+impl Record {
+    pub fn new(options: &GameOptions, stats: GameStats) -> Self {
+        Self {
+            finished: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            mode: options.mode(),
+            seed: stats.seed,
+            elapsed: stats.elapsed,
+            penalty: stats.penalty,
+            sets_found: stats.sets_found,
+            bad_sets: stats.bad_sets,
+            hints: stats.hints,
+            score: stats.score
+        }
+    }
+
+    // What players compete on: time taken, plus any hint penalty
+    pub fn time(&self) -> Duration {
+        self.elapsed + self.penalty
+    }
+}
+
+impl Line for Record {
+    fn to_line(&self) -> String {
+        format!("{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            self.finished, self.mode, self.seed,
+            self.elapsed.as_millis(), self.penalty.as_millis(),
+            self.sets_found, self.bad_sets, self.hints, self.score)
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 9 { return None; };
+
+        Some(Self {
+            finished: fields[0].parse().ok()?,
+            mode: fields[1].to_string(),
+            seed: fields[2].parse().ok()?,
+            elapsed: Duration::from_millis(fields[3].parse().ok()?),
+            penalty: Duration::from_millis(fields[4].parse().ok()?),
+            sets_found: fields[5].parse().ok()?,
+            bad_sets: fields[6].parse().ok()?,
+            hints: fields[7].parse().ok()?,
+            score: fields[8].parse().ok()?
+        })
+    }
+}
+
+// Where russet keeps anything that should outlive the process
+pub fn data_dir() -> Option<PathBuf> {
+    let base = if let Some(dir) = env::var_os("XDG_DATA_HOME") {
+        PathBuf::from(dir)
+    } else if cfg!(windows) {
+        PathBuf::from(env::var_os("APPDATA")?)
+    } else if cfg!(target_os = "macos") {
+        PathBuf::from(env::var_os("HOME")?).join("Library").join("Application Support")
+    } else {
+        PathBuf::from(env::var_os("HOME")?).join(".local").join("share")
+    };
+
+    Some(base.join("russet"))
+}
+
+fn store() -> Option<Store<Record>> {
+    Store::open("stats.tsv", HEADER)
+}
+
+// Civil date from days since the unix epoch, as yyyy-mm-dd. This is Howard Hinnant's
+// days_from_civil run backwards.
+pub fn date(secs: u64) -> String {
+    let days = i64::ffrom(secs / 86400) + 719468;
+    let era = days.div_euclid(146097);
+    let doe = days.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// Lines for the leaderboard overlay: the fastest games in this mode, then totals for it, then
+// how many games have been played in every other mode.
+pub fn leaderboard(records: &[Record], mode: &str, binds: &Bindings) -> Vec<String> {
+    let mut ours: Vec<&Record> = records.iter().filter(|r| r.mode == mode).collect();
+    ours.sort_by_key(|r| (r.time(), -r.score));
+
+    let mut lines = vec![format!("LEADERBOARD ({})", mode), String::new()];
+
+    if ours.is_empty() {
+        lines.push(String::from("No games finished yet"));
+    } else {
+        lines.push(format!("{:>2}  {:>7}  {:>6}  {:>4}  {:<10}  {}", "#", "time", "score", "sets", "date", "seed"));
+        for (i, r) in ours.iter().take(LEADERBOARD_LEN).enumerate() {
+            lines.push(format!("{:>2}  {:>7}  {:>6}  {:>4}  {:<10}  {}",
+                i + 1, fmt_duration(r.time()), r.score, r.sets_found, date(r.finished), r.seed));
+        }
+
+        let games = u32::ffrom(ours.len());
+        let total: Duration = ours.iter().map(|r| r.time()).sum();
+        lines.push(String::new());
+        lines.push(format!("Games: {}    Average time: {}", games, fmt_duration(total / games)));
+        lines.push(format!("Sets: {}    Bad sets: {}    Hints: {}",
+            ours.iter().map(|r| r.sets_found).sum::<u32>(),
+            ours.iter().map(|r| r.bad_sets).sum::<u32>(),
+            ours.iter().map(|r| r.hints).sum::<u32>()));
+    }
+
+    let mut others: Vec<(&str, usize)> = vec![];
+    for r in records.iter().filter(|r| r.mode != mode) {
+        match others.iter_mut().find(|(m, _)| *m == r.mode) {
+            Some((_, n)) => *n += 1,
+            None => others.push((r.mode.as_str(), 1))
+        }
+    }
+
+    if !others.is_empty() {
+        lines.push(String::new());
+        let list: Vec<String> = others.iter().map(|(m, n)| format!("{} ({})", m, n)).collect();
+        lines.push(format!("Other modes: {}", list.join(", ")));
+    }
+
+    lines.push(String::new());
+    lines.push(binds.hints(&[(Action::Leaderboard, "close")]));
+    lines
+}
+
+// Appends a finished game to the default store
+pub fn record_game(options: &GameOptions, stats: GameStats) {
+    store::append_or_warn(store(), &Record::new(options, stats), "game stats");
+}
+
+// Leaderboard for the default store, as overlay lines
+pub fn default_leaderboard(mode: &str, binds: &Bindings) -> Vec<String> {
+    leaderboard(&store::load_or_warn(store(), "game stats"), mode, binds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keymap::Keymap;
+
+    fn rec(mode: &str, secs: u64, score: i32) -> Record {
+        Record {
+            finished: 1_700_000_000,
+            mode: mode.to_string(),
+            seed: secs,
+            elapsed: Duration::from_secs(secs),
+            penalty: Duration::ZERO,
+            sets_found: 27,
+            bad_sets: 0,
+            hints: 0,
+            score
+        }
+    }
+
+    #[test]
+    fn records_round_trip() {
+        let r = Record {
+            finished: 1_700_000_000,
+            mode: String::from("classic"),
+            seed: u64::MAX,
+            elapsed: Duration::from_millis(312_345),
+            penalty: Duration::from_millis(30_000),
+            sets_found: 23,
+            bad_sets: 2,
+            hints: 3,
+            score: -17
+        };
+        assert_eq!(Record::parse(&r.to_line()), Some(r));
+    }
+
+    #[test]
+    fn malformed_lines_dont_parse() {
+        let line = rec("classic", 60, 0).to_line();
+        assert_eq!(Record::parse(""), None);
+        assert_eq!(Record::parse(HEADER), None);
+        assert_eq!(Record::parse(&format!("{}\textra", line)), None);
+        assert_eq!(Record::parse(line.rsplit_once('\t').unwrap().0), None);
+        assert_eq!(Record::parse(&line.replacen("60000", "sixty", 1)), None);
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(date(0), "1970-01-01");
+        assert_eq!(date(86399), "1970-01-01");
+        assert_eq!(date(951_782_400), "2000-02-29");
+        assert_eq!(date(951_868_800), "2000-03-01");
+        assert_eq!(date(19782 * 86400), "2024-02-29");
+        assert_eq!(date(1_700_000_000), "2023-11-14");
+    }
+
+    #[test]
+    fn leaderboard_is_fastest_first_and_only_this_mode() {
+        let records = vec![
+            rec("classic", 300, 10),
+            rec("ultra", 100, 50),
+            rec("classic", 200, 5),
+            rec("classic", 200, 20),
+            rec("ultra", 90, 50),
+        ];
+        let lines = leaderboard(&records, "classic", &Bindings::defaults(Keymap::Qwerty));
+        // Table rows are the ones that start with a rank: (time, score)
+        let rows: Vec<(&str, &str)> = lines.iter()
+            .map(|l| l.split_whitespace().collect::<Vec<_>>())
+            .filter(|f| f.first().and_then(|n| n.parse::<usize>().ok()).is_some())
+            .map(|f| (f[1], f[2]))
+            .collect();
+        // Ties on time go to the higher score
+        assert_eq!(rows, [("03:20", "20"), ("03:20", "5"), ("05:00", "10")]);
+        assert!(lines.iter().any(|l| l == "Other modes: ultra (2)"));
+    }
+
+    #[test]
+    fn leaderboard_with_no_games() {
+        let lines = leaderboard(&[rec("ultra", 100, 0)], "classic", &Bindings::defaults(Keymap::Qwerty));
+        assert!(lines.iter().any(|l| l == "No games finished yet"));
+        assert!(lines.iter().any(|l| l == "Other modes: ultra (1)"));
+    }
+}
@@ -0,0 +1,125 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::marker::PhantomData;
+use std::path::PathBuf;
+
+use log::warn;
+
+use crate::stats::data_dir;
+use crate::util::*;
+
+// Tab-separated files of one row per line, with a header naming the columns. Stats, daily runs
+// and drill answers are all kept this way.
+
+// One row of a store
+pub trait Line: Sized {
+    fn to_line(&self) -> String;
+    fn parse(line: &str) -> Option<Self>;
+}
+
+pub struct Store<T> {
+    path: PathBuf,
+    header: &'static str,
+    rows: PhantomData<T>
+}
+
+impl<T: Line> Store<T> {
+    // The store called name in the user's data directory, if there is one
+    pub fn open(name: &str, header: &'static str) -> Option<Self> {
+        data_dir().map(|dir| Self::at(dir.join(name), header))
+    }
+
+    pub fn at(path: PathBuf, header: &'static str) -> Self {
+        Self { path, header, rows: PhantomData }
+    }
+
+    pub fn append(&self, row: &T) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        };
+
+        let fresh = !self.path.exists();
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        if fresh {
+            writeln!(file, "{}", self.header)?;
+        };
+        writeln!(file, "{}", row.to_line())?;
+        Ok(())
+    }
+
+    // Swaps the first row that matches for row, or appends it if none do. Every other line is
+    // kept as it was, readable or not. The new file is written alongside and moved into place,
+    // so a crash partway through leaves the old one intact.
+    pub fn replace(&self, row: &T, matches: impl Fn(&T) -> bool) -> Result<()> {
+        let text = match fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return self.append(row),
+            Err(err) => return Err(err.into())
+        };
+
+        let mut found = false;
+        let mut lines: Vec<String> = text.lines()
+            .map(|l| if !found && l != self.header && T::parse(l).is_some_and(|r| matches(&r)) {
+                found = true;
+                row.to_line()
+            } else {
+                l.to_string()
+            })
+            .collect();
+        if !found {
+            lines.push(row.to_line());
+        };
+
+        let tmp = self.path.with_extension("tmp");
+        let mut file = fs::File::create(&tmp)?;
+        for line in lines {
+            writeln!(file, "{}", line)?;
+        }
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    // Every row so far, oldest first. A missing file just means no rows yet, and lines that
+    // can't be read are skipped rather than losing everything else.
+    pub fn load(&self) -> Result<Vec<T>> {
+        let text = match fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err.into())
+        };
+
+        Ok(text.lines()
+            .filter(|&l| l != self.header && !l.is_empty())
+            .filter_map(|l| {
+                let row = T::parse(l);
+                if row.is_none() { warn!("skipping unreadable line in {:?}: {:?}", self.path, l); };
+                row
+            })
+            .collect())
+    }
+}
+
+// Everything in store, for callers that can get by without it, so errors are only logged. what
+// names the rows in the log.
+pub fn load_or_warn<T: Line>(store: Option<Store<T>>, what: &str) -> Vec<T> {
+    match store.map(|s| s.load()) {
+        Some(Ok(rows)) => rows,
+        Some(Err(err)) => {
+            warn!("failed to load {}: {:?}", what, err);
+            vec![]
+        },
+        None => vec![]
+    }
+}
+
+// Appends row to store. Failing to save shouldn't take the game down with it, so errors are
+// only logged.
+pub fn append_or_warn<T: Line>(store: Option<Store<T>>, row: &T, what: &str) {
+    match store {
+        Some(store) => if let Err(err) = store.append(row) {
+            warn!("failed to save {}: {:?}", what, err);
+        },
+        None => warn!("no data directory, {} not saved", what)
+    }
+}
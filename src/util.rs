@@ -96,3 +96,9 @@ impl TermSize {
 }
 
 pub static TS: TermSize = TermSize::new();
+
+// mm:ss, minutes keep counting past an hour
+pub fn fmt_duration(d: std::time::Duration) -> String {
+    let secs = d.as_secs();
+    format!("{:02}:{:02}", secs / 60, secs % 60)
+}
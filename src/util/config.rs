@@ -11,6 +11,7 @@ pub struct Scale {
     pub RAW_OVAL: &'static str,
     pub RAW_DIAMOND: &'static str,
     pub RAW_SQUIGGLE: &'static str,
+    pub RAW_HOURGLASS: &'static str,
     pub RAW_QUESTION: &'static str,
 
     // These two are computed. Also, CARD_HEIGHT describes height of card WITHOUT
@@ -27,6 +28,7 @@ impl Scale {
                 RAW_OVAL: &'static str,
                 RAW_DIAMOND: &'static str,
                 RAW_SQUIGGLE: &'static str,
+                RAW_HOURGLASS: &'static str,
                 RAW_QUESTION: &'static str) -> Self {
         
         Self {  SHAPE_HEIGHT,
@@ -34,11 +36,20 @@ impl Scale {
                 RAW_OVAL,
                 RAW_DIAMOND,
                 RAW_SQUIGGLE,
+                RAW_HOURGLASS,
                 RAW_QUESTION,
                 CARD_HEIGHT: SHAPE_HEIGHT + CARD_INTERNAL_MARGIN_VERT * 2,
                 CARD_WIDTH: (SHAPE_HEIGHT * 3) + (SHAPE_SPACING * 4)
         }
     }
+
+    // Same scale, but with cards wide enough to hold `shapes` shapes side by side
+    pub const fn fit(self, shapes: i16) -> Self {
+        Self {
+            CARD_WIDTH: (self.SHAPE_WIDTH * shapes) + (SHAPE_SPACING * (shapes + 1)),
+            ..self
+        }
+    }
 }
 
 pub const SIZE_9: Scale = Scale::new(
@@ -46,6 +57,7 @@ pub const SIZE_9: Scale = Scale::new(
     include_str!("../../txt/9x9/oval.txt"),
     include_str!("../../txt/9x9/diamond.txt"),
     include_str!("../../txt/9x9/squiggle.txt"),
+    include_str!("../../txt/9x9/hourglass.txt"),
     include_str!("../../txt/9x9/question.txt")
 );
 
@@ -54,6 +66,7 @@ pub const SIZE_7: Scale = Scale::new(
     include_str!("../../txt/7x7/oval.txt"),
     include_str!("../../txt/7x7/diamond.txt"),
     include_str!("../../txt/7x7/squiggle.txt"),
+    include_str!("../../txt/7x7/hourglass.txt"),
     include_str!("../../txt/7x7/question.txt")
 );
 
@@ -65,6 +78,7 @@ pub const MIN_WIDTH: i16 = SIZE_7.CARD_WIDTH * 4 + CARD_SPACING_HORIZ * 5;
 pub const COLOR_1: Color = Color::Red;
 pub const COLOR_2: Color = Color::Green;
 pub const COLOR_3: Color = Color::Blue;
+pub const COLOR_4: Color = Color::Cyan;
 
 pub const CARD_BG: Color = Color::White;
 pub const QUESTION_BG: Color = Color::White;
@@ -74,6 +88,12 @@ pub const CARD_BORDER: Color = Color::White;
 pub const SHADOW: Color = Color::Black;
 pub const GOOD_SET: Color = Color::Green;
 pub const BAD_SET: Color = Color::Red;
+pub const HINT: Color = Color::Magenta;
+pub const FOCUS: Color = Color::DarkCyan;
+pub const HOVER: Color = Color::Grey;
+
+pub const OVERLAY_FG: Color = Color::White;
+pub const OVERLAY_BG: Color = Color::DarkGrey;
 
 pub const WIN_MARGIN_VERT: i16 = 1;
 pub const WIN_MARGIN_HORIZ: i16 = 1;
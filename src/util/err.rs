@@ -15,6 +15,16 @@ pub enum SetErrorKind {
     Conversion,
     OutOfBounds,
     IdNotFound,
+
+    // A saved game that can't be read, or doesn't make sense
+    BadSave,
+
+    // A message from the other end of a network game that can't be read, or a connection
+    // that was turned away
+    Protocol,
+
+    // A key bindings file that can't be read, or binds keys that clash
+    BadBindings,
 }
 
 #[derive(Debug)]